@@ -11,6 +11,8 @@ use tauri::{menu::MenuItem, Wry};
 pub struct TrayState {
     pub status_item: Mutex<Option<MenuItem<Wry>>>,
     pub stop_item: Mutex<Option<MenuItem<Wry>>>,
+    pub pause_item: Mutex<Option<MenuItem<Wry>>>,
+    pub resume_item: Mutex<Option<MenuItem<Wry>>>,
 }
 
 use modules::metadata_extractor::VideoMetadata;
@@ -26,20 +28,152 @@ fn get_video_metadata(file_path: String) -> Result<VideoMetadata, String> {
 }
 
 #[tauri::command]
-async fn convert_video_command(
-    window: tauri::Window,
+fn convert_video_command(
+    app: tauri::AppHandle,
     options: modules::ffmpeg_runner::ConversionOptions,
-    state: tauri::State<'_, modules::ffmpeg_runner::ConversionManager>,
 ) -> Result<(), String> {
-    modules::ffmpeg_runner::convert_video(window, options, state).await
+    modules::ffmpeg_runner::enqueue(&app, options)
 }
 
 #[tauri::command]
 async fn cancel_conversion_command(
+    app: tauri::AppHandle,
     id: String,
+    mode: Option<modules::conversion_types::CancelMode>,
     state: tauri::State<'_, modules::ffmpeg_runner::ConversionManager>,
 ) -> Result<(), String> {
-    modules::ffmpeg_runner::cancel_conversion(&id, state).await
+    modules::ffmpeg_runner::cancel_conversion(&app, &id, mode.unwrap_or_default(), state).await
+}
+
+#[tauri::command]
+fn get_queue_command(
+    state: tauri::State<'_, modules::ffmpeg_runner::ConversionManager>,
+) -> modules::job_queue::QueueSnapshot {
+    state.queue.lock().unwrap().snapshot()
+}
+
+#[tauri::command]
+fn move_job_command(app: tauri::AppHandle, id: String, new_index: usize) -> Result<(), String> {
+    modules::ffmpeg_runner::move_job(&app, &id, new_index)
+}
+
+#[tauri::command]
+fn pause_job_command(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    modules::ffmpeg_runner::pause_job(&app, &id)
+}
+
+#[tauri::command]
+fn resume_job_command(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    modules::ffmpeg_runner::resume_job(&app, &id)
+}
+
+#[tauri::command]
+fn remove_job_command(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    modules::ffmpeg_runner::remove_job(&app, &id)
+}
+
+#[tauri::command]
+fn restart_job_command(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    modules::ffmpeg_runner::restart_job(&app, &id)
+}
+
+#[tauri::command]
+fn clear_finished_jobs_command(app: tauri::AppHandle) {
+    modules::ffmpeg_runner::clear_finished_jobs(&app)
+}
+
+#[tauri::command]
+fn set_max_parallel_command(app: tauri::AppHandle, max_parallel: usize) {
+    modules::ffmpeg_runner::set_max_parallel(&app, max_parallel)
+}
+
+fn history_file_path(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    modules::ffmpeg_runner::history_file_path(app).ok_or_else(|| "History unavailable".to_string())
+}
+
+#[tauri::command]
+fn query_history_command(
+    app: tauri::AppHandle,
+    filter: Option<modules::history::HistoryFilter>,
+) -> Result<Vec<modules::history::HistoryEntry>, String> {
+    modules::history::query(&history_file_path(&app)?, &filter.unwrap_or_default())
+}
+
+#[tauri::command]
+fn export_history_csv_command(
+    app: tauri::AppHandle,
+    destination: String,
+    filter: Option<modules::history::HistoryFilter>,
+) -> Result<usize, String> {
+    modules::history::export_csv(
+        &history_file_path(&app)?,
+        &filter.unwrap_or_default(),
+        std::path::Path::new(&destination),
+    )
+}
+
+#[tauri::command]
+fn rerun_history_entry_command(app: tauri::AppHandle, id: String) -> Result<String, String> {
+    modules::ffmpeg_runner::rerun_history_entry(&app, &id)
+}
+
+fn preflight_rules_file_path(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    modules::ffmpeg_runner::preflight_rules_file_path(app)
+        .ok_or_else(|| "Preflight rules unavailable".to_string())
+}
+
+#[tauri::command]
+fn get_preflight_rules_command(
+    app: tauri::AppHandle,
+) -> Result<Vec<modules::preflight::PreflightRule>, String> {
+    Ok(modules::preflight::load_rules(&preflight_rules_file_path(
+        &app,
+    )?))
+}
+
+#[tauri::command]
+fn save_preflight_rules_command(
+    app: tauri::AppHandle,
+    rules: Vec<modules::preflight::PreflightRule>,
+) -> Result<(), String> {
+    modules::preflight::save_rules(&preflight_rules_file_path(&app)?, &rules)
+}
+
+#[tauri::command]
+async fn evaluate_preflight_command(
+    app: tauri::AppHandle,
+    file_path: String,
+) -> Result<modules::preflight::PreflightDecision, String> {
+    modules::ffmpeg_runner::evaluate_preflight(&app, file_path).await
+}
+
+#[tauri::command]
+async fn detect_crop_command(
+    file_path: String,
+) -> Result<modules::crop_detect::CropDetection, String> {
+    modules::ffmpeg_runner::detect_crop(file_path).await
+}
+
+fn job_log_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    modules::ffmpeg_runner::job_log_dir(app).ok_or_else(|| "Log directory unavailable".to_string())
+}
+
+#[tauri::command]
+fn list_job_logs_command(
+    app: tauri::AppHandle,
+    job_id: Option<String>,
+) -> Result<Vec<modules::job_log::JobLogEntry>, String> {
+    modules::job_log::list_logs(&job_log_dir(&app)?, job_id.as_deref())
+}
+
+#[tauri::command]
+fn read_job_log_command(app: tauri::AppHandle, name: String) -> Result<String, String> {
+    modules::job_log::read_log(&job_log_dir(&app)?, &name)
+}
+
+#[tauri::command]
+fn delete_job_log_command(app: tauri::AppHandle, name: String) -> Result<(), String> {
+    modules::job_log::delete_log(&job_log_dir(&app)?, &name)
 }
 
 #[tauri::command]
@@ -53,11 +187,16 @@ fn generate_smart_filename_command(
     metadata: VideoMetadata,
     output_video_codec: Option<String>,
     container: String,
+    filters: Option<modules::conversion_types::VideoFilters>,
 ) -> String {
+    // Crop and scale change the resolution tag, padding does not
+    let output_size = filters
+        .map(|f| modules::command_builder::scaled_dimensions(&f, metadata.width, metadata.height));
     modules::smart_renamer::generate_smart_filename(
         &filename,
         &metadata,
         output_video_codec.as_deref(),
+        output_size,
         &container,
     )
 }
@@ -89,7 +228,7 @@ async fn quit_app_command(
     app: tauri::AppHandle,
     state: tauri::State<'_, modules::ffmpeg_runner::ConversionManager>,
 ) -> Result<(), String> {
-    modules::ffmpeg_runner::kill_all(state).await;
+    modules::ffmpeg_runner::kill_all(&app, state).await;
     app.exit(0);
     Ok(())
 }
@@ -105,6 +244,8 @@ pub fn run() {
         .manage(TrayState {
             status_item: Mutex::new(None),
             stop_item: Mutex::new(None),
+            pause_item: Mutex::new(None),
+            resume_item: Mutex::new(None),
         })
         .setup(|app| {
             use tauri::menu::{Menu, MenuItem};
@@ -114,13 +255,21 @@ pub fn run() {
             let status_i =
                 MenuItem::with_id(app, "status", "No active conversions", false, None::<&str>)?;
             let stop_i = MenuItem::with_id(app, "stop", "Stop Conversion", false, None::<&str>)?;
+            let pause_i = MenuItem::with_id(app, "pause", "Pause Conversion", false, None::<&str>)?;
+            let resume_i =
+                MenuItem::with_id(app, "resume", "Resume Conversion", false, None::<&str>)?;
             let show_i = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
             let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-            let menu = Menu::with_items(app, &[&status_i, &stop_i, &show_i, &quit_i])?;
+            let menu = Menu::with_items(
+                app,
+                &[&status_i, &stop_i, &pause_i, &resume_i, &show_i, &quit_i],
+            )?;
 
             if let Some(state) = app.try_state::<TrayState>() {
                 *state.status_item.lock().unwrap() = Some(status_i.clone());
                 *state.stop_item.lock().unwrap() = Some(stop_i.clone());
+                *state.pause_item.lock().unwrap() = Some(pause_i.clone());
+                *state.resume_item.lock().unwrap() = Some(resume_i.clone());
             }
 
             let _tray = TrayIconBuilder::new()
@@ -134,11 +283,25 @@ pub fn run() {
                             use tauri::Manager;
                             let state =
                                 app_handle.state::<modules::ffmpeg_runner::ConversionManager>();
-                            let _ = modules::ffmpeg_runner::cancel_current(state).await;
+                            let _ =
+                                modules::ffmpeg_runner::cancel_current(&app_handle, state).await;
                         });
                     }
+                    "pause" => {
+                        modules::ffmpeg_runner::pause_current(app.app_handle());
+                    }
+                    "resume" => {
+                        modules::ffmpeg_runner::resume_current(app.app_handle());
+                    }
                     "quit" => {
-                        app.exit(0);
+                        let app_handle = app.app_handle().clone();
+                        tauri::async_runtime::spawn(async move {
+                            use tauri::Manager;
+                            let state =
+                                app_handle.state::<modules::ffmpeg_runner::ConversionManager>();
+                            modules::ffmpeg_runner::kill_all(&app_handle, state).await;
+                            app_handle.exit(0);
+                        });
                     }
                     "show" => {
                         if let Some(window) = app.get_webview_window("main") {
@@ -151,6 +314,8 @@ pub fn run() {
                 })
                 .build(app)?;
 
+            modules::ffmpeg_runner::restore_queue(app.handle());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -158,6 +323,24 @@ pub fn run() {
             get_video_metadata,
             convert_video_command,
             cancel_conversion_command,
+            get_queue_command,
+            move_job_command,
+            pause_job_command,
+            resume_job_command,
+            remove_job_command,
+            restart_job_command,
+            clear_finished_jobs_command,
+            set_max_parallel_command,
+            list_job_logs_command,
+            read_job_log_command,
+            delete_job_log_command,
+            query_history_command,
+            export_history_csv_command,
+            rerun_history_entry_command,
+            get_preflight_rules_command,
+            save_preflight_rules_command,
+            evaluate_preflight_command,
+            detect_crop_command,
             clean_filename_command,
             generate_smart_filename_command,
             get_gpu_capabilities_command,
@@ -187,3 +370,15 @@ pub fn set_stop_enabled(app: &tauri::AppHandle, enabled: bool) {
         }
     }
 }
+
+pub fn set_pause_enabled(app: &tauri::AppHandle, pause_enabled: bool, resume_enabled: bool) {
+    use tauri::Manager;
+    if let Some(state) = app.try_state::<TrayState>() {
+        if let Some(item) = state.pause_item.lock().unwrap().as_ref() {
+            let _ = item.set_enabled(pause_enabled);
+        }
+        if let Some(item) = state.resume_item.lock().unwrap().as_ref() {
+            let _ = item.set_enabled(resume_enabled);
+        }
+    }
+}
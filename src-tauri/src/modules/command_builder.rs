@@ -0,0 +1,1857 @@
+use crate::modules::conversion_types::{
+    parse_bitrate_kbps, AudioCodec, AudioStrategy, ChannelLayout, Container, CropRect,
+    EncoderFamily, EncoderPreset, HdrMode, LoudnessTarget, RateControl, SubtitleStrategy,
+    VideoCodec, VideoFilters,
+};
+use crate::modules::ffmpeg_runner::ConversionOptions;
+use crate::modules::loudness::{self, LoudnessMeasurement};
+use crate::modules::metadata_extractor::{
+    AudioStream, ColorInfo, HdrFormat, SubtitleStream, VideoMetadata,
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InputSpec {
+    pub options: Vec<String>, // Flags placed before this "-i"
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CodecSpec {
+    pub stream: String, // Stream specifier, e.g. "v", "a" or "s:1"
+    pub codec: String,
+    pub params: Vec<(String, String)>, // Extra encoder flags, e.g. ("-b:a", "128k")
+}
+
+/// Bitmap subtitle track drawn over the filtered video. Its canvas has the
+/// size of the source picture, so it is scaled and moved along with the
+/// crop, scale and pad filters.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubtitleOverlay {
+    pub stream: String,             // e.g. "0:s:1"
+    pub canvas: Option<(u32, u32)>, // Scaled canvas size, None to keep it
+    pub x: i64,                     // Canvas position on the picture
+    pub y: i64,
+}
+
+/// Output audio track that is encoded rather than copied.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConvertedAudio {
+    pub stream_index: u32,    // Input stream
+    pub track: usize,         // Output audio track number
+    pub filters: Vec<String>, // Channel layout changes, loudnorm comes after them
+}
+
+/// Source track left out of the output, with the reason shown to the user.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DroppedTrack {
+    pub index: u32,
+    pub codec_name: String,
+    pub language: Option<String>,
+    pub reason: String,
+}
+
+/// Label of the filtered video when the plan needs a filter graph (overlays).
+pub const VIDEO_OUT_LABEL: &str = "[vout]";
+
+// Share of a target size kept for container headers and indexes
+const CONTAINER_OVERHEAD_RATIO: f64 = 0.02;
+// Below this a target size leaves too little for a watchable picture
+const MIN_TARGET_VIDEO_KBPS: f64 = 100.0;
+// Assumed for copied audio without a reported bitrate and for lossless audio.
+// High on purpose: overshooting a size cap is worse than undershooting it.
+const UNKNOWN_AUDIO_KBPS: f64 = 640.0;
+
+#[cfg(windows)]
+const NULL_OUTPUT: &str = "NUL";
+#[cfg(not(windows))]
+const NULL_OUTPUT: &str = "/dev/null";
+
+/// Everything ffmpeg needs for one conversion, built without touching the
+/// filesystem so it can be compared and inspected before anything is spawned.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommandPlan {
+    pub global_flags: Vec<String>,
+    pub inputs: Vec<InputSpec>,
+    pub maps: Vec<String>,
+    pub codecs: Vec<CodecSpec>,
+    pub video_filters: Vec<String>,
+    // Image subtitles drawn over the filtered video. When set the video goes
+    // through -filter_complex and is mapped as VIDEO_OUT_LABEL.
+    pub subtitle_overlay: Option<SubtitleOverlay>,
+    pub output_flags: Vec<String>,
+    pub output_path: String,
+    pub dropped_tracks: Vec<DroppedTrack>,
+    // What the output should contain, checked once ffmpeg is done
+    pub expected_video_codec: String, // ffprobe codec name
+    pub expected_audio_streams: usize,
+    pub expected_subtitle_streams: usize,
+    // Bitrate-targeted encode that needs an analysis pass first, see for_pass
+    pub two_pass: bool,
+    pub output_frames: Option<u64>, // Frame count of the output, for progress
+    // Encoded output audio tracks, in output order
+    pub converted_audio: Vec<ConvertedAudio>,
+    // Loudness analysis run, see for_loudness_measurement
+    pub measures_loudness: bool,
+}
+
+impl CommandPlan {
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = self.global_flags.clone();
+
+        for input in &self.inputs {
+            args.extend(input.options.iter().cloned());
+            args.push("-i".to_string());
+            args.push(input.path.clone());
+        }
+
+        for map in &self.maps {
+            args.push("-map".to_string());
+            args.push(map.clone());
+        }
+
+        for codec in &self.codecs {
+            args.push(format!("-c:{}", codec.stream));
+            args.push(codec.codec.clone());
+            for (flag, value) in &codec.params {
+                args.push(flag.clone());
+                args.push(value.clone());
+            }
+        }
+
+        if let Some(overlay) = &self.subtitle_overlay {
+            // Subtitles go on the finished picture, after every video filter
+            let mut graph = String::new();
+            let video = if self.video_filters.is_empty() {
+                "[0:v:0]"
+            } else {
+                graph.push_str(&format!("[0:v:0]{}[base];", self.video_filters.join(",")));
+                "[base]"
+            };
+            let subtitles = match overlay.canvas {
+                Some((width, height)) => {
+                    graph.push_str(&format!(
+                        "[{}]scale={}:{}[subs];",
+                        overlay.stream, width, height
+                    ));
+                    "[subs]".to_string()
+                }
+                None => format!("[{}]", overlay.stream),
+            };
+            graph.push_str(&format!("{}{}overlay", video, subtitles));
+            if (overlay.x, overlay.y) != (0, 0) {
+                graph.push_str(&format!("=x={}:y={}", overlay.x, overlay.y));
+            }
+            graph.push_str(VIDEO_OUT_LABEL);
+            args.push("-filter_complex".to_string());
+            args.push(graph);
+        } else if !self.video_filters.is_empty() {
+            args.push("-vf".to_string());
+            args.push(self.video_filters.join(","));
+        }
+
+        args.extend(self.output_flags.iter().cloned());
+        args.push(self.output_path.clone());
+        args
+    }
+
+    /// The command for one pass of a two-pass encode. Pass 1 only analyses
+    /// the video and writes its statistics to `passlog`, pass 2 reads them
+    /// back for the real encode.
+    pub fn for_pass(&self, pass: u8, passlog: &str) -> CommandPlan {
+        let mut plan = self.clone();
+
+        let video = &mut plan.codecs[0];
+        if video.codec == "libx265" {
+            // libx265 ignores -pass, its settings are ':'-separated x265 options
+            push_param(
+                &mut video.params,
+                "-x265-params",
+                format!("pass={}:stats={}", pass, escape_x265_value(passlog)),
+            );
+        } else {
+            video.params.push(("-pass".to_string(), pass.to_string()));
+            video
+                .params
+                .push(("-passlogfile".to_string(), passlog.to_string()));
+        }
+
+        if pass == 1 {
+            // Same picture as pass 2, nothing else, and no file written
+            plan.maps.truncate(1);
+            plan.codecs.truncate(1);
+            plan.converted_audio.clear();
+            plan.output_flags = vec![
+                "-an".to_string(),
+                "-sn".to_string(),
+                "-f".to_string(),
+                "null".to_string(),
+            ];
+            plan.output_path = NULL_OUTPUT.to_string();
+        }
+
+        plan
+    }
+
+    /// Analysis run that passes every converted audio track through loudnorm
+    /// and nothing else. The measurements are printed to stderr on exit,
+    /// see `loudness::parse_measurements`.
+    pub fn for_loudness_measurement(&self, target: &LoudnessTarget) -> CommandPlan {
+        let mut plan = self.clone();
+
+        let graph = self
+            .converted_audio
+            .iter()
+            .enumerate()
+            .map(|(n, audio)| {
+                let mut chain = audio.filters.clone();
+                chain.push(loudness::measure_filter(target, n));
+                format!(
+                    "[0:{}]{}[loudness{}]",
+                    audio.stream_index,
+                    chain.join(","),
+                    n
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        plan.global_flags
+            .extend(["-filter_complex".to_string(), graph]);
+        plan.maps = (0..self.converted_audio.len())
+            .map(|n| format!("[loudness{}]", n))
+            .collect();
+        plan.codecs.clear();
+        plan.video_filters.clear();
+        plan.subtitle_overlay = None;
+        plan.output_flags = vec!["-f".to_string(), "null".to_string()];
+        plan.output_path = NULL_OUTPUT.to_string();
+        plan.two_pass = false;
+        plan.output_frames = None;
+        plan.measures_loudness = true;
+        plan
+    }
+
+    /// Normalizes the converted audio tracks with what was measured for
+    /// them. Tracks without a usable measurement are left alone.
+    pub fn apply_loudness(
+        &mut self,
+        target: &LoudnessTarget,
+        measurements: &[LoudnessMeasurement],
+    ) {
+        let tracks: Vec<usize> = self.converted_audio.iter().map(|a| a.track).collect();
+        for track in tracks {
+            let measured = measurements
+                .iter()
+                .find(|m| m.track == track && m.is_usable());
+            if let Some(measured) = measured {
+                self.push_audio_filter(track, &loudness::normalize_filter(target, measured));
+            }
+        }
+    }
+
+    /// Appends to the filter chain of one output audio track, a second
+    /// "-filter:a:N" would replace the first.
+    fn push_audio_filter(&mut self, track: usize, filter: &str) {
+        let flag = format!("-filter:a:{}", track);
+        match self.output_flags.iter().position(|f| *f == flag) {
+            Some(i) => {
+                let chain = &mut self.output_flags[i + 1];
+                chain.push(',');
+                chain.push_str(filter);
+            }
+            None => {
+                self.output_flags.push(flag);
+                self.output_flags.push(filter.to_string());
+            }
+        }
+    }
+
+    /// Shell-like rendering for logs, quoting arguments that contain spaces.
+    pub fn to_command_line(&self) -> String {
+        let args: Vec<String> = self
+            .to_args()
+            .into_iter()
+            .map(|arg| {
+                if arg.contains(' ') {
+                    format!("\"{}\"", arg)
+                } else {
+                    arg
+                }
+            })
+            .collect();
+        format!("ffmpeg {}", args.join(" "))
+    }
+}
+
+/// Encoder option lists such as `-x265-params` are merged into one
+/// ':'-separated flag, ffmpeg only keeps the last one given.
+fn push_param(params: &mut Vec<(String, String)>, flag: &str, value: String) {
+    if flag.ends_with("-params") {
+        if let Some((_, list)) = params.iter_mut().find(|(f, _)| f == flag) {
+            list.push(':');
+            list.push_str(&value);
+            return;
+        }
+    }
+    params.push((flag.to_string(), value));
+}
+
+/// Escapes the separators in a `-x265-params` value, such as the ':' after
+/// the drive letter of a Windows path.
+fn escape_x265_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ':' | '=' | '\\' | '\'') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// VideoToolbox constant quality, 1-100 with higher being better, for a CRF
+/// on the 0-51 scale.
+fn videotoolbox_quality(crf: u8) -> u32 {
+    (100 - crf.min(51) as u32 * 100 / 51).max(1)
+}
+
+/// Rate control flags for the encoder family. Combinations the family does
+/// not support are rejected by `ConversionOptions::validate` beforehand.
+fn rate_control_params(options: &ConversionOptions, params: &mut Vec<(String, String)>) {
+    let codec = options.video_codec;
+    let family = codec.family();
+    let max_kbps = options.max_bitrate.as_deref().and_then(parse_bitrate_kbps);
+    let buffer_kbps = options.buffer_size.as_deref().and_then(parse_bitrate_kbps);
+
+    match options.rate_control.unwrap_or_default() {
+        RateControl::Quality => {
+            // The CRF scale is shared: x264/x265/SVT-AV1 CRF, NVENC constant
+            // quality, QSV ICQ, VAAPI and AMF QP. VideoToolbox counts quality
+            // the other way round. A target size sets a bitrate instead.
+            if let Some(crf) = options.crf.filter(|_| options.target_size_mb.is_none()) {
+                match family {
+                    EncoderFamily::X26x | EncoderFamily::SvtAv1 => {
+                        push_param(params, "-crf", crf.to_string())
+                    }
+                    // Without "-b:v 0" libaom and libvpx cap the CRF at their default bitrate
+                    EncoderFamily::Aom | EncoderFamily::Vpx => {
+                        push_param(params, "-crf", crf.to_string());
+                        push_param(params, "-b:v", "0".to_string());
+                    }
+                    EncoderFamily::Nvenc => {
+                        push_param(params, "-rc", "vbr".to_string());
+                        push_param(params, "-cq", crf.to_string());
+                    }
+                    EncoderFamily::Qsv => push_param(params, "-global_quality", crf.to_string()),
+                    EncoderFamily::Vaapi => push_param(params, "-qp", crf.to_string()),
+                    EncoderFamily::Amf => {
+                        push_param(params, "-rc", "cqp".to_string());
+                        push_param(params, "-qp_i", crf.to_string());
+                        push_param(params, "-qp_p", crf.to_string());
+                    }
+                    EncoderFamily::VideoToolbox if codec != VideoCodec::ProresVideoToolbox => {
+                        push_param(params, "-q:v", videotoolbox_quality(crf).to_string())
+                    }
+                    // ProRes profiles have a fixed rate, there is no quality to set
+                    EncoderFamily::VideoToolbox => {}
+                }
+            }
+
+            // Capped quality: the VBV keeps peaks under max_bitrate
+            if let Some(max_kbps) = max_kbps {
+                let buffer_kbps = buffer_kbps.unwrap_or(max_kbps * 2.0);
+                push_param(params, "-maxrate", format!("{:.0}k", max_kbps));
+                push_param(params, "-bufsize", format!("{:.0}k", buffer_kbps));
+            }
+        }
+        RateControl::ConstantBitrate => {
+            let Some(bitrate_kbps) = options
+                .video_bitrate
+                .as_deref()
+                .and_then(parse_bitrate_kbps)
+            else {
+                return;
+            };
+            // One second of buffer unless told otherwise
+            let buffer_kbps = buffer_kbps.unwrap_or(bitrate_kbps);
+
+            match family {
+                EncoderFamily::Nvenc => push_param(params, "-rc", "cbr".to_string()),
+                EncoderFamily::Vaapi => push_param(params, "-rc_mode", "CBR".to_string()),
+                _ => {}
+            }
+            push_param(params, "-b:v", format!("{:.0}k", bitrate_kbps));
+            // QSV switches to CBR when maxrate equals the bitrate
+            push_param(params, "-minrate", format!("{:.0}k", bitrate_kbps));
+            push_param(params, "-maxrate", format!("{:.0}k", bitrate_kbps));
+            push_param(params, "-bufsize", format!("{:.0}k", buffer_kbps));
+
+            // Filler data so the stream really is constant, not just capped
+            match codec.ffmpeg_name() {
+                "libx264" => push_param(params, "-x264-params", "nal-hrd=cbr".to_string()),
+                "libx265" => push_param(params, "-x265-params", "strict-cbr=1".to_string()),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn video_codec_spec(options: &ConversionOptions) -> CodecSpec {
+    let codec = options.video_codec;
+    let mut params = Vec::new();
+
+    // "fast" is NVENC's balanced p4. Presets the encoder cannot take are
+    // rejected by `ConversionOptions::validate`.
+    let preset = options.preset.unwrap_or(EncoderPreset::Fast);
+    if let Ok(Some((flag, value))) = preset.encoder_param(codec) {
+        push_param(&mut params, flag, value.to_string());
+    }
+
+    rate_control_params(options, &mut params);
+
+    if let Some(profile) = &options.profile {
+        params.push(("-profile:v".to_string(), profile.clone()));
+    }
+
+    if let Some(tune) = &options.tune {
+        params.push(("-tune".to_string(), tune.clone()));
+    }
+
+    CodecSpec {
+        stream: "v".to_string(),
+        codec: codec.ffmpeg_name().to_string(),
+        params,
+    }
+}
+
+/// Replaces the video encode with a stream copy of the input video, which
+/// keeps its codec. Not possible once the picture goes through a filter.
+fn copy_video(plan: &mut CommandPlan, metadata: &VideoMetadata) -> Result<(), String> {
+    if plan.subtitle_overlay.is_some() || !plan.video_filters.is_empty() {
+        return Err("The video cannot be copied when it is filtered".to_string());
+    }
+
+    plan.codecs[0] = CodecSpec {
+        stream: "v".to_string(),
+        codec: "copy".to_string(),
+        params: Vec::new(),
+    };
+    plan.expected_video_codec = metadata.video_codec.clone();
+    Ok(())
+}
+
+/// Remux: the video is copied and only the container and the other tracks
+/// change, with the fixes a bare stream copy tends to need.
+fn plan_remux(
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+    plan: &mut CommandPlan,
+) -> Result<(), String> {
+    let container = Container::from_path(&options.output_path);
+    if !container.can_hold_video(&metadata.video_codec) {
+        let extension = std::path::Path::new(&options.output_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_uppercase();
+        return Err(format!(
+            "{} video cannot be copied into {}, it has to be encoded",
+            metadata.video_codec, extension
+        ));
+    }
+
+    copy_video(plan, metadata)?;
+
+    // Sources without proper timestamps (AVI, raw MPEG-TS) fail to mux otherwise
+    plan.inputs[0].options.push("-fflags".to_string());
+    plan.inputs[0].options.push("+genpts".to_string());
+    plan.output_flags.push("-avoid_negative_ts".to_string());
+    plan.output_flags.push("make_zero".to_string());
+
+    if matches!(container, Container::Mp4 | Container::Mov) {
+        // Index at the start of the file so playback can begin before it is fully read
+        plan.output_flags.push("-movflags".to_string());
+        plan.output_flags.push("+faststart".to_string());
+        // Apple players only accept HEVC tagged as hvc1, stream copies keep hev1
+        if metadata.video_codec == "hevc" {
+            plan.codecs[0]
+                .params
+                .push(("-tag:v".to_string(), "hvc1".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks output track 0 of the given type as the default one and clears the
+/// flag on the others, so players pick the track that was moved first.
+fn set_default_track(plan: &mut CommandPlan, stream_type: &str, count: usize) {
+    for i in 0..count {
+        plan.output_flags
+            .push(format!("-disposition:{}:{}", stream_type, i));
+        plan.output_flags
+            .push(if i == 0 { "default" } else { "0" }.to_string());
+    }
+}
+
+fn audio_codec_spec(stream: String, codec: AudioCodec) -> CodecSpec {
+    CodecSpec {
+        stream,
+        codec: codec.ffmpeg_name().to_string(),
+        params: Vec::new(),
+    }
+}
+
+/// Bitrate of a track copied as it is in kb/s, as far as it can be known
+/// before encoding.
+fn copied_track_kbps(stream: &AudioStream) -> f64 {
+    stream
+        .bit_rate
+        .map(|b| b as f64 / 1000.0)
+        .unwrap_or(UNKNOWN_AUDIO_KBPS)
+}
+
+/// The audio bitrate setting is meant for stereo, wider tracks get a share
+/// per channel within what the format allows.
+fn encoded_track_kbps(codec: AudioCodec, stereo_kbps: f64, channels: u32) -> Option<f64> {
+    if !codec.takes_bitrate() {
+        return None;
+    }
+    let kbps = (stereo_kbps * channels.max(1) as f64 / 2.0).round();
+    Some(codec.max_bitrate_kbps().map_or(kbps, |max| kbps.min(max)))
+}
+
+/// Channel count of a converted track: the layout asked for, within what
+/// the encoder takes. Tracks are never upmixed.
+fn output_channels(stream: &AudioStream, layout: ChannelLayout, codec: AudioCodec) -> u32 {
+    stream
+        .channels
+        .min(layout.max_channels())
+        .min(codec.max_channels())
+}
+
+/// Fold-down to stereo after ITU-R BS.775: centre and surrounds at -3 dB
+/// on both sides, LFE left out. '<' rescales each side so the sum cannot
+/// clip. Layouts whose channel names are not known get ffmpeg's own matrix.
+fn stereo_downmix(stream: &AudioStream) -> String {
+    let (left, right): (&[&str], &[&str]) = match stream.channel_layout.as_deref() {
+        Some("5.0") | Some("5.1") => (&["BL"], &["BR"]),
+        Some("5.0(side)") | Some("5.1(side)") => (&["SL"], &["SR"]),
+        Some("7.1") => (&["BL", "SL"], &["BR", "SR"]),
+        _ => return "aformat=channel_layouts=stereo".to_string(),
+    };
+    let side = |front: &str, surrounds: &[&str]| {
+        let mut sum = format!("{}<{}+0.707*FC", front, front);
+        for channel in surrounds {
+            sum.push_str(&format!("+0.707*{}", channel));
+        }
+        sum
+    };
+    format!("pan=stereo|{}|{}", side("FL", left), side("FR", right))
+}
+
+/// Filter folding `stream` down to `channels`, None when it already fits.
+fn downmix_filter(stream: &AudioStream, channels: u32) -> Option<String> {
+    if channels >= stream.channels {
+        return None;
+    }
+    Some(match channels {
+        2 => stereo_downmix(stream),
+        6 => "aformat=channel_layouts=5.1".to_string(),
+        n => format!("aformat=channel_layouts={}c", n),
+    })
+}
+
+/// Maps the audio tracks and returns their total bitrate in kb/s, which a
+/// target size has to leave room for.
+fn plan_audio(
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+    plan: &mut CommandPlan,
+) -> Result<f64, String> {
+    if metadata.audio_streams.is_empty() {
+        plan.output_flags.push("-an".to_string());
+        return Ok(0.0);
+    }
+
+    let audio_codec = options.audio_codec.unwrap_or(AudioCodec::Aac);
+    let stereo_kbps = options
+        .audio_bitrate
+        .as_deref()
+        .and_then(parse_bitrate_kbps)
+        .unwrap_or(128.0);
+    let strategy = options.audio_strategy.unwrap_or_default();
+    let codec = if strategy == AudioStrategy::CopyAll {
+        AudioCodec::Copy
+    } else {
+        audio_codec
+    };
+    // A stereo track cannot be copied out of a surround one
+    let compat_codec = if codec == AudioCodec::Copy {
+        AudioCodec::Aac
+    } else {
+        codec
+    };
+    let add_compat = options.stereo_compat_track.unwrap_or(false);
+    let layout_of = |stream: &AudioStream| {
+        options
+            .track_channel_layouts
+            .as_ref()
+            .and_then(|layouts| layouts.get(&stream.index).copied())
+            .or(options.channel_layout)
+            .unwrap_or_default()
+    };
+
+    // Input tracks in output order, with the stream each one is mapped from.
+    // With a track index, that track goes first and the others follow.
+    let first = options
+        .audio_track_index
+        .filter(|index| metadata.audio_streams.iter().any(|s| s.index == *index));
+    let sources: Vec<(u32, &AudioStream)> = match strategy {
+        AudioStrategy::FirstTrack => {
+            // First track or a specific index
+            let stream = match options.audio_track_index {
+                Some(index) => metadata
+                    .audio_streams
+                    .iter()
+                    .find(|s| s.index == index)
+                    .ok_or_else(|| format!("Audio track {} not found in the input", index))?,
+                None => &metadata.audio_streams[0],
+            };
+            vec![(stream.index, stream)]
+        }
+        AudioStrategy::CopyAll | AudioStrategy::ConvertAll => {
+            let (moved, rest): (Vec<&AudioStream>, Vec<&AudioStream>) = metadata
+                .audio_streams
+                .iter()
+                .partition(|s| Some(s.index) == first);
+            moved
+                .into_iter()
+                .chain(rest)
+                .map(|s| (s.index, s))
+                .collect()
+        }
+    };
+
+    let mut maps = Vec::new();
+    let mut codecs = vec![audio_codec_spec("a".to_string(), codec)];
+    let mut bitrates = Vec::new(); // (output track, kb/s) of the encoded tracks
+    let mut compat_tracks = Vec::new();
+    let mut total_kbps = 0.0;
+
+    for (map_index, stream) in sources {
+        let track = maps.len();
+        maps.push(format!("0:{}", map_index));
+        let channels = if codec == AudioCodec::Copy {
+            total_kbps += copied_track_kbps(stream);
+            stream.channels
+        } else {
+            let channels = output_channels(stream, layout_of(stream), codec);
+            plan.converted_audio.push(ConvertedAudio {
+                stream_index: stream.index,
+                track,
+                filters: downmix_filter(stream, channels).into_iter().collect(),
+            });
+            let kbps = encoded_track_kbps(codec, stereo_kbps, channels);
+            bitrates.extend(kbps.map(|kbps| (track, kbps)));
+            total_kbps += kbps.unwrap_or(UNKNOWN_AUDIO_KBPS);
+            channels
+        };
+
+        if add_compat && channels > 2 {
+            let track = maps.len();
+            maps.push(format!("0:{}", map_index));
+            plan.converted_audio.push(ConvertedAudio {
+                stream_index: stream.index,
+                track,
+                filters: vec![stereo_downmix(stream)],
+            });
+            if compat_codec != codec {
+                codecs.push(audio_codec_spec(format!("a:{}", track), compat_codec));
+            }
+            let kbps = encoded_track_kbps(compat_codec, stereo_kbps, 2);
+            bitrates.extend(kbps.map(|kbps| (track, kbps)));
+            total_kbps += kbps.unwrap_or(UNKNOWN_AUDIO_KBPS);
+            compat_tracks.push(track);
+        }
+    }
+
+    let track_count = maps.len();
+    plan.expected_audio_streams = track_count;
+    if strategy != AudioStrategy::FirstTrack && first.is_none() && compat_tracks.is_empty() {
+        plan.maps.push("0:a".to_string());
+    } else {
+        plan.maps.extend(maps);
+    }
+
+    // One "-b:a" while every track gets the same rate
+    let uniform =
+        bitrates.len() == track_count && bitrates.iter().all(|(_, kbps)| *kbps == bitrates[0].1);
+    if uniform {
+        codecs[0]
+            .params
+            .push(("-b:a".to_string(), format!("{}k", bitrates[0].1)));
+    } else {
+        for (track, kbps) in &bitrates {
+            codecs[0]
+                .params
+                .push((format!("-b:a:{}", track), format!("{}k", kbps)));
+        }
+    }
+    plan.codecs.extend(codecs);
+
+    for audio in plan.converted_audio.clone() {
+        for filter in &audio.filters {
+            plan.push_audio_filter(audio.track, filter);
+        }
+    }
+
+    if strategy != AudioStrategy::FirstTrack && first.is_some() {
+        set_default_track(plan, "a", track_count);
+    } else {
+        for track in &compat_tracks {
+            plan.output_flags.push(format!("-disposition:a:{}", track));
+            plan.output_flags.push("0".to_string());
+        }
+    }
+    for track in &compat_tracks {
+        plan.output_flags.push(format!("-metadata:s:a:{}", track));
+        plan.output_flags.push("title=Stereo".to_string());
+    }
+
+    Ok(total_kbps)
+}
+
+/// Video bitrate in kb/s that fits the output into `target_size_mb` once the
+/// audio and the container overhead are taken out.
+pub fn target_video_kbps(
+    target_size_mb: f64,
+    duration_seconds: f64,
+    audio_kbps: f64,
+) -> Result<u64, String> {
+    if duration_seconds <= 0.0 {
+        return Err("A target size needs the video duration".to_string());
+    }
+
+    let total_kbits = target_size_mb * 1024.0 * 1024.0 * 8.0 / 1000.0;
+    let video_kbps = total_kbits * (1.0 - CONTAINER_OVERHEAD_RATIO) / duration_seconds - audio_kbps;
+    if video_kbps < MIN_TARGET_VIDEO_KBPS {
+        return Err(format!(
+            "{} MB is too small for {:.0} seconds of video ({:.0} kb/s left for the picture)",
+            target_size_mb,
+            duration_seconds,
+            video_kbps.max(0.0)
+        ));
+    }
+
+    Ok(video_kbps as u64)
+}
+
+/// Size of the picture after crop and scale, before padding. Scaling keeps
+/// the aspect ratio, never enlarges, and rounds to the even sizes 4:2:0
+/// encoders need.
+pub fn scaled_dimensions(filters: &VideoFilters, width: u32, height: u32) -> (u32, u32) {
+    let (width, height) = filters
+        .crop
+        .map(|c| (c.width, c.height))
+        .unwrap_or((width, height));
+    if width == 0 || height == 0 {
+        return (width, height);
+    }
+
+    let fit_width = filters
+        .max_width
+        .map(|max| max as f64 / width as f64)
+        .unwrap_or(1.0);
+    let fit_height = filters
+        .max_height
+        .map(|max| max as f64 / height as f64)
+        .unwrap_or(1.0);
+    let scale = fit_width.min(fit_height);
+    if scale >= 1.0 {
+        return (width, height);
+    }
+
+    let even = |size: f64| ((size / 2.0).round() as u32 * 2).max(2);
+    (even(width as f64 * scale), even(height as f64 * scale))
+}
+
+/// Size of the encoded picture, padding included.
+pub fn output_dimensions(filters: &VideoFilters, width: u32, height: u32) -> (u32, u32) {
+    let (width, height) = scaled_dimensions(filters, width, height);
+    (
+        filters.pad_width.unwrap_or(width).max(width),
+        filters.pad_height.unwrap_or(height).max(height),
+    )
+}
+
+/// Appends the deinterlace, crop, scale, pad and fps filters. Burned-in
+/// subtitles come after them, see plan_burn_in.
+fn plan_video_filters(
+    filters: &VideoFilters,
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+    plan: &mut CommandPlan,
+) -> Result<(), String> {
+    if filters.deinterlace {
+        plan.video_filters.push("bwdif=mode=send_frame".to_string());
+    }
+
+    let known_size = metadata.width > 0 && metadata.height > 0;
+
+    if let Some(crop) = filters.crop {
+        if known_size
+            && (crop.x + crop.width > metadata.width || crop.y + crop.height > metadata.height)
+        {
+            return Err(format!(
+                "Crop {}x{} at {},{} does not fit in the {}x{} picture",
+                crop.width, crop.height, crop.x, crop.y, metadata.width, metadata.height
+            ));
+        }
+        plan.video_filters.push(format!(
+            "crop={}:{}:{}:{}",
+            crop.width, crop.height, crop.x, crop.y
+        ));
+    }
+
+    if filters.max_width.is_some() || filters.max_height.is_some() {
+        if known_size {
+            let before = filters
+                .crop
+                .map(|c| (c.width, c.height))
+                .unwrap_or((metadata.width, metadata.height));
+            let (width, height) = scaled_dimensions(filters, metadata.width, metadata.height);
+            if (width, height) != before {
+                plan.video_filters
+                    .push(format!("scale={}:{}", width, height));
+            }
+        } else {
+            // Size unknown until ffmpeg reads the stream, let it do the fitting
+            plan.video_filters.push(format!(
+                "scale=w='min(iw,{})':h='min(ih,{})':force_original_aspect_ratio=decrease:force_divisible_by=2",
+                filters.max_width.map_or("iw".to_string(), |w| w.to_string()),
+                filters.max_height.map_or("ih".to_string(), |h| h.to_string())
+            ));
+        }
+    }
+
+    if filters.pad_width.is_some() || filters.pad_height.is_some() {
+        let (width, height) = scaled_dimensions(filters, metadata.width, metadata.height);
+        let pad_width = filters.pad_width.unwrap_or(width);
+        let pad_height = filters.pad_height.unwrap_or(height);
+        if known_size && (pad_width < width || pad_height < height) {
+            return Err(format!(
+                "Cannot pad the {}x{} picture to the smaller size {}x{}",
+                width, height, pad_width, pad_height
+            ));
+        }
+        plan.video_filters.push(format!(
+            "pad={}:{}:(ow-iw)/2:(oh-ih)/2",
+            if pad_width > 0 {
+                pad_width.to_string()
+            } else {
+                "iw".to_string()
+            },
+            if pad_height > 0 {
+                pad_height.to_string()
+            } else {
+                "ih".to_string()
+            }
+        ));
+    }
+
+    if let Some(fps) = filters.fps {
+        plan.video_filters.push(format!("fps={}", fps));
+        let duration = if options.duration_seconds > 0.0 {
+            options.duration_seconds
+        } else {
+            metadata.duration
+        };
+        if duration > 0.0 {
+            plan.output_frames = Some((duration * fps).round() as u64);
+        }
+    }
+
+    Ok(())
+}
+
+/// PQ or HLG to 8-bit BT.709. zscale reads the source transfer from the
+/// frames, tonemap only works on linear light.
+const TONE_MAP_FILTERS: &str = "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p";
+
+/// x265 syntax: chromaticities in 0.00002 units, luminance in 0.0001 cd/m²,
+/// green first.
+fn x265_hdr_params(color: &ColorInfo) -> Vec<String> {
+    let mut params = vec!["hdr10=1".to_string(), "repeat-headers=1".to_string()];
+    if let Some(md) = color.mastering_display {
+        let xy =
+            |(x, y): (f64, f64)| format!("({},{})", (x * 50000.0).round(), (y * 50000.0).round());
+        params.push(format!(
+            "master-display=G{}B{}R{}WP{}L({},{})",
+            xy(md.green),
+            xy(md.blue),
+            xy(md.red),
+            xy(md.white_point),
+            (md.max_luminance * 10000.0).round(),
+            (md.min_luminance * 10000.0).round()
+        ));
+    }
+    if let Some(cll) = color.content_light {
+        params.push(format!("max-cll={},{}", cll.max_content, cll.max_average));
+    }
+    params
+}
+
+/// SVT-AV1 takes the same data as plain numbers.
+fn svtav1_hdr_params(color: &ColorInfo) -> Vec<String> {
+    let mut params = vec!["enable-hdr=1".to_string()];
+    if let Some(md) = color.mastering_display {
+        let xy = |(x, y): (f64, f64)| format!("({:.4},{:.4})", x, y);
+        params.push(format!(
+            "mastering-display=G{}B{}R{}WP{}L({:.4},{:.4})",
+            xy(md.green),
+            xy(md.blue),
+            xy(md.red),
+            xy(md.white_point),
+            md.max_luminance,
+            md.min_luminance
+        ));
+    }
+    if let Some(cll) = color.content_light {
+        params.push(format!(
+            "content-light={},{}",
+            cll.max_content, cll.max_average
+        ));
+    }
+    params
+}
+
+/// Tone-maps an HDR source to SDR, or keeps it HDR in a 10-bit encode with
+/// its colour tags and mastering metadata. SDR sources are left alone.
+fn plan_hdr(
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+    plan: &mut CommandPlan,
+) -> Result<(), String> {
+    let color = &metadata.color;
+    if color.hdr == Some(HdrFormat::DolbyVision) && color.dolby_vision_compatibility == Some(0) {
+        return Err(format!(
+            "Dolby Vision profile {} has no HDR10 base layer and cannot be converted",
+            color.dolby_vision_profile.unwrap_or_default()
+        ));
+    }
+    let Some(hdr) = color.base_layer_hdr() else {
+        return Ok(());
+    };
+
+    let codec = options.video_codec;
+    let mode = options.hdr_mode.unwrap_or(if codec.supports_hdr10() {
+        HdrMode::Preserve
+    } else {
+        HdrMode::ToneMap
+    });
+    let params = &mut plan.codecs[0].params;
+
+    match mode {
+        HdrMode::ToneMap => {
+            // After deinterlacing and ahead of crop and scale, which are
+            // cheaper and more accurate on the SDR picture
+            let at = plan
+                .video_filters
+                .iter()
+                .take_while(|f| f.starts_with("bwdif"))
+                .count();
+            plan.video_filters.insert(at, TONE_MAP_FILTERS.to_string());
+            for flag in ["-color_primaries", "-color_trc", "-colorspace"] {
+                push_param(params, flag, "bt709".to_string());
+            }
+        }
+        HdrMode::Preserve => {
+            if !codec.supports_hdr10() {
+                return Err(format!(
+                    "{} cannot keep HDR, use libx265, libsvtav1, libaom-av1, hevc_nvenc or hevc_qsv, or tone-map to SDR",
+                    codec.ffmpeg_name()
+                ));
+            }
+            let pix_fmt = match codec.family() {
+                EncoderFamily::Nvenc | EncoderFamily::Qsv => "p010le",
+                _ => "yuv420p10le",
+            };
+            push_param(params, "-pix_fmt", pix_fmt.to_string());
+            if codec.output_codec_name() == "hevc" && options.profile.is_none() {
+                push_param(params, "-profile:v", "main10".to_string());
+            }
+
+            let tag = |value: &Option<String>, default: &str| {
+                value.clone().unwrap_or_else(|| default.to_string())
+            };
+            let transfer = if hdr == HdrFormat::Hlg {
+                "arib-std-b67"
+            } else {
+                "smpte2084"
+            };
+            push_param(
+                params,
+                "-color_primaries",
+                tag(&color.color_primaries, "bt2020"),
+            );
+            push_param(params, "-color_trc", tag(&color.color_transfer, transfer));
+            push_param(params, "-colorspace", tag(&color.color_space, "bt2020nc"));
+
+            // HLG needs no static metadata, the tags are enough
+            match codec {
+                _ if hdr == HdrFormat::Hlg => {}
+                VideoCodec::Libx265 => {
+                    push_param(params, "-x265-params", x265_hdr_params(color).join(":"))
+                }
+                VideoCodec::LibSvtAv1 => {
+                    push_param(params, "-svtav1-params", svtav1_hdr_params(color).join(":"))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Bitmap subtitle formats, which can only be burned in with an overlay.
+pub fn is_image_subtitle(codec_name: &str) -> bool {
+    matches!(
+        codec_name,
+        "hdmv_pgs_subtitle" | "pgssub" | "dvd_subtitle" | "dvdsub" | "dvb_subtitle" | "xsub"
+    )
+}
+
+/// Escapes a path for use as a filter option value. Windows separators are
+/// turned into '/' and the drive colon is escaped ("C\:/Movies/film.mkv").
+fn escape_filter_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .replace(':', "\\:")
+        .replace('\'', "'\\\\\\''")
+}
+
+/// Where the bitmap subtitle canvas goes once the picture was cropped,
+/// scaled and padded. Subtitles placed in cropped-away bars end up outside
+/// the picture, like the bars themselves.
+fn overlay_placement(
+    filters: Option<&VideoFilters>,
+    metadata: &VideoMetadata,
+) -> (Option<(u32, u32)>, i64, i64) {
+    let (width, height) = (metadata.width, metadata.height);
+    let Some(filters) = filters.filter(|_| width > 0 && height > 0) else {
+        return (None, 0, 0);
+    };
+
+    let crop = filters.crop.unwrap_or(CropRect {
+        width,
+        height,
+        x: 0,
+        y: 0,
+    });
+    let (scaled_width, scaled_height) = scaled_dimensions(filters, width, height);
+    let (out_width, out_height) = output_dimensions(filters, width, height);
+    let scale_x = scaled_width as f64 / crop.width as f64;
+    let scale_y = scaled_height as f64 / crop.height as f64;
+
+    let canvas = (
+        (width as f64 * scale_x).round() as u32,
+        (height as f64 * scale_y).round() as u32,
+    );
+    // pad keeps its offsets even for 4:2:0
+    let pad_offset = |outer: u32, inner: u32| (((outer - inner) / 2) & !1) as i64;
+    let x = pad_offset(out_width, scaled_width) - (crop.x as f64 * scale_x).round() as i64;
+    let y = pad_offset(out_height, scaled_height) - (crop.y as f64 * scale_y).round() as i64;
+    ((canvas != (width, height)).then_some(canvas), x, y)
+}
+
+/// Hard-subs one subtitle track: the subtitles filter for text formats, an
+/// overlay for bitmap formats. Either is drawn on the finished picture, so
+/// it has to run once the other video filters are planned. The track is
+/// `subtitle_track_index` (an input stream index) or the first subtitle
+/// stream.
+fn plan_burn_in(
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+    plan: &mut CommandPlan,
+) -> Result<(), String> {
+    // Nothing else is written out, the selected track ends up in the picture
+    plan.output_flags.push("-sn".to_string());
+
+    let position = match options.subtitle_track_index {
+        Some(index) => metadata
+            .subtitle_streams
+            .iter()
+            .position(|s| s.index == index)
+            .ok_or_else(|| format!("Subtitle track {} not found in the input", index))?,
+        None if metadata.subtitle_streams.is_empty() => return Ok(()),
+        None => 0,
+    };
+    let stream = &metadata.subtitle_streams[position];
+
+    if is_image_subtitle(&stream.codec_name) {
+        let (canvas, x, y) = overlay_placement(options.filters.as_ref(), metadata);
+        plan.subtitle_overlay = Some(SubtitleOverlay {
+            stream: format!("0:s:{}", position),
+            canvas,
+            x,
+            y,
+        });
+        plan.maps[0] = VIDEO_OUT_LABEL.to_string();
+    } else {
+        // "si" counts subtitle streams only, not absolute stream indexes
+        plan.video_filters.push(format!(
+            "subtitles=filename='{}':si={}",
+            escape_filter_path(&options.input_path),
+            position
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_text_subtitle(codec_name: &str) -> bool {
+    matches!(
+        codec_name,
+        "subrip" | "srt" | "ass" | "ssa" | "webvtt" | "mov_text" | "text"
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SubtitleAction {
+    Copy,
+    Convert(&'static str),
+    Drop(String),
+}
+
+/// Decides what happens to one source subtitle track in the output container.
+fn subtitle_action(codec_name: &str, container: Container) -> SubtitleAction {
+    let is_text = is_text_subtitle(codec_name);
+
+    match container {
+        Container::Mp4 | Container::Mov => {
+            if codec_name == "mov_text" {
+                SubtitleAction::Copy
+            } else if is_text {
+                // 'mov_text' is the only text subtitle format MP4 carries natively
+                SubtitleAction::Convert("mov_text")
+            } else if container == Container::Mp4 && codec_name == "dvd_subtitle" {
+                SubtitleAction::Copy
+            } else {
+                SubtitleAction::Drop(format!(
+                    "{} subtitles cannot be stored in MP4/MOV",
+                    codec_name
+                ))
+            }
+        }
+        Container::Mkv => {
+            if codec_name == "mov_text" {
+                SubtitleAction::Convert("srt")
+            } else {
+                SubtitleAction::Copy
+            }
+        }
+        Container::Webm => {
+            if codec_name == "webvtt" {
+                SubtitleAction::Copy
+            } else if is_text {
+                SubtitleAction::Convert("webvtt")
+            } else {
+                SubtitleAction::Drop(format!("WebM only supports WebVTT, not {}", codec_name))
+            }
+        }
+        Container::Avi => SubtitleAction::Drop("AVI has no standard subtitle track".to_string()),
+        // Unknown container, let ffmpeg decide
+        Container::Other => SubtitleAction::Copy,
+    }
+}
+
+/// Maps the given subtitle tracks one by one, each with its own codec, and
+/// records the ones the output container cannot hold.
+fn plan_subtitle_tracks(streams: &[&SubtitleStream], container: Container, plan: &mut CommandPlan) {
+    let mut output_index = 0;
+
+    for stream in streams {
+        let codec = match subtitle_action(&stream.codec_name, container) {
+            SubtitleAction::Copy => "copy",
+            SubtitleAction::Convert(codec) => codec,
+            SubtitleAction::Drop(reason) => {
+                plan.dropped_tracks.push(DroppedTrack {
+                    index: stream.index,
+                    codec_name: stream.codec_name.clone(),
+                    language: stream.language.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        plan.maps.push(format!("0:{}", stream.index));
+        plan.codecs.push(CodecSpec {
+            stream: format!("s:{}", output_index),
+            codec: codec.to_string(),
+            params: Vec::new(),
+        });
+        output_index += 1;
+    }
+
+    plan.expected_subtitle_streams += output_index;
+}
+
+fn plan_subtitles(
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+    plan: &mut CommandPlan,
+) -> Result<(), String> {
+    let container = Container::from_path(&options.output_path);
+
+    match options.subtitle_strategy {
+        // Drawn on the finished picture, see build_command
+        Some(SubtitleStrategy::BurnIn) => {}
+        Some(SubtitleStrategy::CopyAll) => {
+            let mut streams: Vec<&SubtitleStream> = metadata.subtitle_streams.iter().collect();
+
+            // With a track index, that track goes first and becomes the default
+            if let Some(position) = options
+                .subtitle_track_index
+                .and_then(|index| streams.iter().position(|s| s.index == index))
+            {
+                let selected = streams.remove(position);
+                streams.insert(0, selected);
+                plan_subtitle_tracks(&streams, container, plan);
+                if !plan
+                    .dropped_tracks
+                    .iter()
+                    .any(|t| t.index == selected.index)
+                {
+                    set_default_track(plan, "s", plan.expected_subtitle_streams);
+                }
+            } else {
+                plan_subtitle_tracks(&streams, container, plan);
+            }
+        }
+        Some(SubtitleStrategy::Ignore) => {
+            plan.output_flags.push("-sn".to_string());
+        }
+        None => {
+            if let Some(index) = options.subtitle_track_index {
+                let stream = metadata
+                    .subtitle_streams
+                    .iter()
+                    .find(|s| s.index == index)
+                    .ok_or_else(|| format!("Subtitle track {} not found in the input", index))?;
+                plan_subtitle_tracks(&[stream], container, plan);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn build_command(
+    options: &ConversionOptions,
+    metadata: &VideoMetadata,
+) -> Result<CommandPlan, String> {
+    let mut plan = CommandPlan {
+        global_flags: vec!["-y".to_string()],
+        inputs: vec![InputSpec {
+            options: Vec::new(),
+            path: options.input_path.clone(),
+        }],
+        // Map video stream first (required for video output)
+        maps: vec!["0:v".to_string()],
+        codecs: vec![video_codec_spec(options)],
+        video_filters: Vec::new(),
+        subtitle_overlay: None,
+        output_flags: Vec::new(),
+        output_path: options.output_path.clone(),
+        dropped_tracks: Vec::new(),
+        expected_video_codec: options.video_codec.output_codec_name().to_string(),
+        expected_audio_streams: 0,
+        expected_subtitle_streams: 0,
+        two_pass: false,
+        output_frames: options.total_frames,
+        converted_audio: Vec::new(),
+        measures_loudness: false,
+    };
+
+    let audio_kbps = plan_audio(options, metadata, &mut plan)?;
+    plan_subtitles(options, metadata, &mut plan)?;
+    if let Some(filters) = &options.filters {
+        plan_video_filters(filters, options, metadata, &mut plan)?;
+    }
+
+    if let Some(target_size_mb) = options.target_size_mb {
+        if options.remux.unwrap_or(false) {
+            return Err("A target size needs the video to be encoded, not remuxed".to_string());
+        }
+        let duration = if options.duration_seconds > 0.0 {
+            options.duration_seconds
+        } else {
+            metadata.duration
+        };
+        let video_kbps = target_video_kbps(target_size_mb, duration, audio_kbps)?;
+        plan.codecs[0]
+            .params
+            .push(("-b:v".to_string(), format!("{}k", video_kbps)));
+        plan.two_pass = true;
+    }
+
+    let remux = options.remux.unwrap_or(false);
+    if !remux {
+        plan_hdr(options, metadata, &mut plan)?;
+    }
+    if options.subtitle_strategy == Some(SubtitleStrategy::BurnIn) {
+        plan_burn_in(options, metadata, &mut plan)?;
+    }
+    // A remux copies the stream, HDR included
+    if remux {
+        plan_remux(options, metadata, &mut plan)?;
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::metadata_extractor::ContentLightLevel;
+    use serde_json::json;
+
+    fn options(overrides: serde_json::Value) -> ConversionOptions {
+        let mut value = json!({
+            "id": "job",
+            "input_path": "in.mkv",
+            "output_path": "out.mp4",
+            "video_codec": "libx264",
+            "duration_seconds": 120.0,
+            "crf": 23,
+            "subtitle_strategy": "ignore",
+        });
+        for (key, override_value) in overrides.as_object().unwrap() {
+            value[key] = override_value.clone();
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    // 1080p H.264 with a 5.1 and a stereo track, a text and a PGS subtitle
+    fn metadata() -> VideoMetadata {
+        serde_json::from_value(json!({
+            "duration": 120.0,
+            "width": 1920,
+            "height": 1080,
+            "format": "matroska,webm",
+            "video_codec": "h264",
+            "audio_streams": [
+                { "index": 1, "codec_name": "ac3", "language": "eng", "channels": 6,
+                  "bit_rate": 640000, "channel_layout": "5.1(side)" },
+                { "index": 2, "codec_name": "aac", "language": "fre", "channels": 2,
+                  "channel_layout": "stereo" },
+            ],
+            "subtitle_streams": [
+                { "index": 3, "codec_name": "subrip", "language": "eng" },
+                { "index": 4, "codec_name": "hdmv_pgs_subtitle", "language": "fre" },
+            ],
+            "size": 1_500_000_000u64,
+            "total_frames": 2880,
+        }))
+        .unwrap()
+    }
+
+    fn args(plan: &CommandPlan) -> String {
+        plan.to_args().join(" ")
+    }
+
+    fn build(overrides: serde_json::Value) -> CommandPlan {
+        build_command(&options(overrides), &metadata()).unwrap()
+    }
+
+    // Encoder flags of the video stream
+    fn video_params(overrides: serde_json::Value) -> String {
+        build(overrides).codecs[0]
+            .params
+            .iter()
+            .map(|(flag, value)| format!("{} {}", flag, value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn crf_encode() {
+        assert_eq!(
+            args(&build(json!({}))),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a aac -b:a 384k \
+             -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn nvenc_takes_a_constant_quality() {
+        let plan = build(json!({ "video_codec": "h264_nvenc", "preset": "slow" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v h264_nvenc -preset p5 -rc vbr -cq 23 -c:a aac \
+             -b:a 384k -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn copy_all_audio() {
+        let plan = build(json!({ "audio_strategy": "copy_all" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:a -c:v libx264 -preset fast -crf 23 -c:a copy \
+             -sn out.mp4"
+        );
+        assert_eq!(plan.expected_audio_streams, 2);
+    }
+
+    #[test]
+    fn convert_all_audio() {
+        let plan = build(json!({ "audio_strategy": "convert_all", "audio_bitrate": "192k" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:a -c:v libx264 -preset fast -crf 23 -c:a aac \
+             -b:a:0 576k -b:a:1 192k -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn first_track_by_index() {
+        let plan = build(json!({ "audio_track_index": 2, "audio_bitrate": "0.2M" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:2 -c:v libx264 -preset fast -crf 23 -c:a aac \
+             -b:a 200k -sn out.mp4"
+        );
+
+        let missing = build_command(&options(json!({ "audio_track_index": 7 })), &metadata());
+        assert_eq!(missing.unwrap_err(), "Audio track 7 not found in the input");
+    }
+
+    #[test]
+    fn lossless_audio_has_no_bitrate() {
+        let plan = build(json!({ "audio_codec": "flac", "audio_bitrate": "192k" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a flac \
+             -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn copy_all_subtitles_to_mp4() {
+        let plan = build(json!({ "subtitle_strategy": "copy_all" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -map 0:3 -c:v libx264 -preset fast -crf 23 -c:a aac \
+             -b:a 384k -c:s:0 mov_text out.mp4"
+        );
+        // PGS is a bitmap format MP4 cannot hold
+        assert_eq!(plan.dropped_tracks.len(), 1);
+        assert_eq!(plan.dropped_tracks[0].index, 4);
+        assert_eq!(plan.expected_subtitle_streams, 1);
+    }
+
+    #[test]
+    fn copy_all_subtitles_to_mkv() {
+        let plan = build(json!({ "subtitle_strategy": "copy_all", "output_path": "out.mkv" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -map 0:3 -map 0:4 -c:v libx264 -preset fast -crf 23 \
+             -c:a aac -b:a 384k -c:s:0 copy -c:s:1 copy out.mkv"
+        );
+        assert!(plan.dropped_tracks.is_empty());
+    }
+
+    #[test]
+    fn text_burn_in() {
+        let plan = build(json!({ "subtitle_strategy": "burn_in" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a aac -b:a 384k \
+             -vf subtitles=filename='in.mkv':si=0 -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn image_burn_in() {
+        let plan = build(json!({ "subtitle_strategy": "burn_in", "subtitle_track_index": 4 }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map [vout] -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a aac \
+             -b:a 384k -filter_complex [0:v:0][0:s:1]overlay[vout] -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn text_burn_in_after_picture_filters() {
+        let plan = build(json!({
+            "subtitle_strategy": "burn_in",
+            "filters": {
+                "deinterlace": true,
+                "crop": { "width": 1920, "height": 800, "x": 0, "y": 140 },
+                "max_width": 1280,
+                "fps": 25.0,
+            },
+        }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a aac -b:a 384k \
+             -vf bwdif=mode=send_frame,crop=1920:800:0:140,scale=1280:534,fps=25,\
+             subtitles=filename='in.mkv':si=0 -sn out.mp4"
+        );
+        assert_eq!(plan.output_frames, Some(3000));
+    }
+
+    #[test]
+    fn image_burn_in_follows_crop_scale_and_pad() {
+        let plan = build(json!({
+            "subtitle_strategy": "burn_in",
+            "subtitle_track_index": 4,
+            "filters": {
+                "crop": { "width": 1920, "height": 800, "x": 0, "y": 140 },
+                "max_width": 1280,
+                "pad_width": 1280,
+                "pad_height": 720,
+            },
+        }));
+        // The 1080p subtitle canvas is shrunk like the picture and moved up
+        // by the cropped top bar, less the top padding
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map [vout] -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a aac \
+             -b:a 384k -filter_complex [0:v:0]crop=1920:800:0:140,scale=1280:534,\
+             pad=1280:720:(ow-iw)/2:(oh-ih)/2[base];[0:s:1]scale=1280:721[subs];\
+             [base][subs]overlay=x=0:y=-1[vout] -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn copy_video_keeps_the_source_codec() {
+        let mut plan = build(json!({}));
+        copy_video(&mut plan, &metadata()).unwrap();
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v copy -c:a aac -b:a 384k -sn out.mp4"
+        );
+        assert_eq!(plan.expected_video_codec, "h264");
+    }
+
+    #[test]
+    fn copy_video_refuses_burned_in_subtitles() {
+        let mut plan = build(json!({ "subtitle_strategy": "burn_in" }));
+        assert!(copy_video(&mut plan, &metadata()).is_err());
+    }
+
+    #[test]
+    fn remux_copies_the_video() {
+        let plan = build(json!({ "remux": true, "audio_strategy": "copy_all" }));
+        assert_eq!(
+            args(&plan),
+            "-y -fflags +genpts -i in.mkv -map 0:v -map 0:a -c:v copy -c:a copy -sn \
+             -avoid_negative_ts make_zero -movflags +faststart out.mp4"
+        );
+    }
+
+    #[test]
+    fn remux_rejects_burned_in_subtitles() {
+        let error = build_command(
+            &options(json!({ "remux": true, "subtitle_strategy": "burn_in" })),
+            &metadata(),
+        )
+        .unwrap_err();
+        assert_eq!(error, "The video cannot be copied when it is filtered");
+    }
+
+    #[test]
+    fn copy_all_moves_the_selected_track_first() {
+        let plan = build(json!({ "audio_strategy": "copy_all", "audio_track_index": 2 }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:2 -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a copy \
+             -disposition:a:0 default -disposition:a:1 0 -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn two_pass_target_size() {
+        let plan = build(json!({ "target_size_mb": 100.0 }));
+        assert!(plan.two_pass);
+        assert_eq!(
+            args(&plan.for_pass(1, "ffmpeg2pass")),
+            format!(
+                "-y -i in.mkv -map 0:v -c:v libx264 -preset fast -b:v 6466k -pass 1 \
+                 -passlogfile ffmpeg2pass -an -sn -f null {}",
+                NULL_OUTPUT
+            )
+        );
+        assert_eq!(
+            args(&plan.for_pass(2, "ffmpeg2pass")),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -b:v 6466k -pass 2 \
+             -passlogfile ffmpeg2pass -c:a aac -b:a 384k -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn capped_crf() {
+        let plan = build(json!({ "max_bitrate": "8M" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 -maxrate 8000k \
+             -bufsize 16000k -c:a aac -b:a 384k -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn crf_per_encoder_family() {
+        let crf = |codec: &str| video_params(json!({ "video_codec": codec }));
+        assert_eq!(crf("libsvtav1"), "-preset 8 -crf 23");
+        assert_eq!(crf("libaom-av1"), "-cpu-used 5 -crf 23 -b:v 0");
+        assert_eq!(crf("libvpx-vp9"), "-cpu-used 4 -crf 23 -b:v 0");
+        assert_eq!(crf("h264_qsv"), "-preset fast -global_quality 23");
+        assert_eq!(crf("hevc_vaapi"), "-qp 23");
+        assert_eq!(crf("hevc_videotoolbox"), "-q:v 55");
+        assert_eq!(crf("prores_videotoolbox"), "");
+        assert_eq!(
+            crf("h264_amf"),
+            "-quality balanced -rc cqp -qp_i 23 -qp_p 23"
+        );
+    }
+
+    #[test]
+    fn preset_per_encoder_family() {
+        let speed = |codec: &str, preset: &str| {
+            video_params(json!({ "video_codec": codec, "preset": preset, "crf": null }))
+        };
+        assert_eq!(speed("libx265", "slow"), "-preset slow");
+        assert_eq!(speed("hevc_nvenc", "veryfast"), "-preset p3");
+        assert_eq!(speed("hevc_nvenc", "p6"), "-preset p6");
+        assert_eq!(speed("libsvtav1", "medium"), "-preset 6");
+        assert_eq!(speed("libaom-av1", "fast"), "-cpu-used 5");
+        assert_eq!(speed("libvpx-vp9", "slower"), "-cpu-used 1");
+        assert_eq!(speed("h264_qsv", "ultrafast"), "-preset veryfast");
+        assert_eq!(speed("hevc_amf", "veryslow"), "-quality quality");
+        assert_eq!(speed("hevc_vaapi", "slow"), "");
+        assert_eq!(speed("hevc_videotoolbox", "slow"), "");
+    }
+
+    #[test]
+    fn nvenc_presets_are_rejected_elsewhere() {
+        let error = options(json!({ "video_codec": "libsvtav1", "preset": "p4" }))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "Preset \"p4\" is only valid for NVENC encoders, not libsvtav1"
+        );
+        assert!(
+            options(json!({ "video_codec": "h264_nvenc", "preset": "p4" }))
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn constant_bitrate() {
+        let plan = build(json!({ "rate_control": "constant_bitrate", "video_bitrate": "4000k" }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -b:v 4000k -minrate 4000k \
+             -maxrate 4000k -bufsize 4000k -x264-params nal-hrd=cbr -c:a aac -b:a 384k \
+             -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn remux_rejects_filters() {
+        let error = build_command(
+            &options(json!({ "remux": true, "filters": { "deinterlace": true } })),
+            &metadata(),
+        )
+        .unwrap_err();
+        assert_eq!(error, "The video cannot be copied when it is filtered");
+    }
+
+    #[test]
+    fn picture_filters_run_in_order() {
+        let plan = build(json!({
+            "filters": {
+                "deinterlace": true,
+                "crop": { "width": 1920, "height": 800, "x": 0, "y": 140 },
+                "max_width": 1280,
+                "pad_width": 1280,
+                "pad_height": 720,
+                "fps": 25.0,
+            },
+        }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 -c:a aac -b:a 384k \
+             -vf bwdif=mode=send_frame,crop=1920:800:0:140,scale=1280:534,\
+             pad=1280:720:(ow-iw)/2:(oh-ih)/2,fps=25 -sn out.mp4"
+        );
+        assert_eq!(plan.output_frames, Some(3000));
+    }
+
+    #[test]
+    fn two_pass_merges_x265_params() {
+        let mut metadata = metadata();
+        metadata.video_codec = "hevc".to_string();
+        metadata.color.hdr = Some(HdrFormat::Hdr10);
+        metadata.color.content_light = Some(ContentLightLevel {
+            max_content: 1000,
+            max_average: 400,
+        });
+        let plan = build_command(
+            &options(json!({ "video_codec": "libx265", "target_size_mb": 100.0 })),
+            &metadata,
+        )
+        .unwrap();
+        assert_eq!(
+            args(&plan.for_pass(2, "ffmpeg2pass")),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx265 -preset fast -b:v 6466k \
+             -pix_fmt yuv420p10le -profile:v main10 -color_primaries bt2020 -color_trc smpte2084 \
+             -colorspace bt2020nc \
+             -x265-params hdr10=1:repeat-headers=1:max-cll=1000,400:pass=2:stats=ffmpeg2pass \
+             -c:a aac -b:a 384k -sn out.mp4"
+        );
+
+        let windows = plan.for_pass(1, r"C:\Temp\ffmpeg2pass");
+        assert_eq!(
+            windows.codecs[0].params.last().unwrap().1,
+            r"hdr10=1:repeat-headers=1:max-cll=1000,400:pass=1:stats=C\:\\Temp\\ffmpeg2pass"
+        );
+    }
+
+    #[test]
+    fn dolby_vision_keeps_its_base_layer() {
+        let dolby_vision = |compatibility, transfer: &str| {
+            let mut metadata = metadata();
+            metadata.video_codec = "hevc".to_string();
+            metadata.color.hdr = Some(HdrFormat::DolbyVision);
+            metadata.color.dolby_vision_profile = Some(8);
+            metadata.color.dolby_vision_compatibility = Some(compatibility);
+            metadata.color.color_transfer = Some(transfer.to_string());
+            let options = options(json!({ "video_codec": "libx265", "hdr_mode": "preserve" }));
+            let plan = build_command(&options, &metadata).unwrap();
+            plan.codecs[0]
+                .params
+                .iter()
+                .map(|(flag, value)| format!("{} {}", flag, value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        assert_eq!(
+            dolby_vision(1, "smpte2084"),
+            "-preset fast -crf 23 -pix_fmt yuv420p10le -profile:v main10 \
+             -color_primaries bt2020 -color_trc smpte2084 -colorspace bt2020nc \
+             -x265-params hdr10=1:repeat-headers=1"
+        );
+        // Profile 8.4 is HLG underneath, without HDR10 metadata
+        assert_eq!(
+            dolby_vision(4, "arib-std-b67"),
+            "-preset fast -crf 23 -pix_fmt yuv420p10le -profile:v main10 \
+             -color_primaries bt2020 -color_trc arib-std-b67 -colorspace bt2020nc"
+        );
+    }
+
+    #[test]
+    fn tone_map_runs_before_crop_and_subtitles() {
+        let mut metadata = metadata();
+        metadata.video_codec = "hevc".to_string();
+        metadata.color.hdr = Some(HdrFormat::Hdr10);
+        let plan = build_command(
+            &options(json!({
+                "subtitle_strategy": "burn_in",
+                "hdr_mode": "tone_map",
+                "filters": {
+                    "deinterlace": true,
+                    "crop": { "width": 1920, "height": 800, "x": 0, "y": 140 },
+                },
+            })),
+            &metadata,
+        )
+        .unwrap();
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -c:v libx264 -preset fast -crf 23 \
+             -color_primaries bt709 -color_trc bt709 -colorspace bt709 -c:a aac -b:a 384k -vf \
+             bwdif=mode=send_frame,zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+             tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p,\
+             crop=1920:800:0:140,subtitles=filename='in.mkv':si=0 -sn out.mp4"
+        );
+    }
+
+    #[test]
+    fn loudness_measures_after_the_downmix() {
+        let target = LoudnessTarget::default();
+        let mut plan = build(json!({
+            "audio_strategy": "convert_all",
+            "stereo_compat_track": true,
+            "loudness": { "integrated": -23.0, "true_peak": -1.0, "range": 11.0 },
+        }));
+        assert_eq!(
+            args(&plan.for_loudness_measurement(&target)),
+            format!(
+                "-y -filter_complex \
+                 [0:1]loudnorm@track0=I=-23:TP=-1:LRA=11:print_format=json[loudness0];\
+                 [0:1]pan=stereo|FL<FL+0.707*FC+0.707*SL|FR<FR+0.707*FC+0.707*SR,\
+                 loudnorm@track1=I=-23:TP=-1:LRA=11:print_format=json[loudness1];\
+                 [0:2]loudnorm@track2=I=-23:TP=-1:LRA=11:print_format=json[loudness2] \
+                 -i in.mkv -map [loudness0] -map [loudness1] -map [loudness2] -f null {}",
+                NULL_OUTPUT
+            )
+        );
+
+        let measured = |track, stream_index, integrated| LoudnessMeasurement {
+            stream_index,
+            track,
+            integrated,
+            true_peak: -2.0,
+            range: 6.0,
+            threshold: -30.0,
+            target_offset: 0.0,
+        };
+        // Track 2 is the stereo source, measured silent
+        plan.apply_loudness(
+            &target,
+            &[
+                measured(0, 1, -20.0),
+                measured(1, 1, -18.0),
+                measured(2, 2, f64::NEG_INFINITY),
+            ],
+        );
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -map 0:1 -map 0:2 -c:v libx264 -preset fast -crf 23 \
+             -c:a aac -b:a:0 384k -b:a:1 128k -b:a:2 128k -filter:a:1 \
+             pan=stereo|FL<FL+0.707*FC+0.707*SL|FR<FR+0.707*FC+0.707*SR,loudnorm=I=-23:TP=-1:\
+             LRA=11:measured_I=-18.00:measured_TP=-2.00:measured_LRA=6.00:measured_thresh=-30.00:\
+             offset=0.00:linear=true,aresample=48000 -disposition:a:1 0 \
+             -metadata:s:a:1 title=Stereo -sn -filter:a:0 loudnorm=I=-23:TP=-1:LRA=11:\
+             measured_I=-20.00:measured_TP=-2.00:measured_LRA=6.00:measured_thresh=-30.00:\
+             offset=0.00:linear=true,aresample=48000 out.mp4"
+        );
+    }
+
+    #[test]
+    fn stereo_downmix_with_compatibility_track() {
+        let plan = build(json!({
+            "audio_strategy": "convert_all",
+            "channel_layout": "stereo",
+            "track_channel_layouts": { "1": "keep" },
+            "stereo_compat_track": true,
+        }));
+        assert_eq!(
+            args(&plan),
+            "-y -i in.mkv -map 0:v -map 0:1 -map 0:1 -map 0:2 -c:v libx264 -preset fast -crf 23 \
+             -c:a aac -b:a:0 384k -b:a:1 128k -b:a:2 128k \
+             -filter:a:1 pan=stereo|FL<FL+0.707*FC+0.707*SL|FR<FR+0.707*FC+0.707*SR \
+             -disposition:a:1 0 -metadata:s:a:1 title=Stereo -sn out.mp4"
+        );
+        assert_eq!(plan.expected_audio_streams, 3);
+    }
+}
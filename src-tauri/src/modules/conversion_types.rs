@@ -0,0 +1,601 @@
+use serde::{Deserialize, Serialize};
+
+// Values sent by the frontend and stored in presets. Unknown strings are
+// rejected by serde when the command arguments are deserialized, instead of
+// silently falling back to a default.
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    #[serde(rename = "libx264")]
+    Libx264,
+    #[serde(rename = "libx265")]
+    Libx265,
+    #[serde(rename = "libsvtav1")]
+    LibSvtAv1,
+    #[serde(rename = "libaom-av1")]
+    LibAomAv1,
+    #[serde(rename = "libvpx-vp9")]
+    LibVpxVp9,
+    #[serde(rename = "h264_nvenc")]
+    H264Nvenc,
+    #[serde(rename = "hevc_nvenc")]
+    HevcNvenc,
+    #[serde(rename = "h264_qsv")]
+    H264Qsv,
+    #[serde(rename = "hevc_qsv")]
+    HevcQsv,
+    #[serde(rename = "vp9_qsv")]
+    Vp9Qsv,
+    #[serde(rename = "h264_vaapi")]
+    H264Vaapi,
+    #[serde(rename = "hevc_vaapi")]
+    HevcVaapi,
+    #[serde(rename = "vp9_vaapi")]
+    Vp9Vaapi,
+    #[serde(rename = "h264_videotoolbox")]
+    H264VideoToolbox,
+    #[serde(rename = "hevc_videotoolbox")]
+    HevcVideoToolbox,
+    #[serde(rename = "prores_videotoolbox")]
+    ProresVideoToolbox,
+    #[serde(rename = "h264_amf")]
+    H264Amf,
+    #[serde(rename = "hevc_amf")]
+    HevcAmf,
+}
+
+/// Encoder implementations that share the same rate-control and preset flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderFamily {
+    X26x,
+    SvtAv1,
+    Aom,
+    Vpx,
+    Nvenc,
+    Qsv,
+    Vaapi,
+    VideoToolbox,
+    Amf,
+}
+
+impl VideoCodec {
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            VideoCodec::Libx264 => "libx264",
+            VideoCodec::Libx265 => "libx265",
+            VideoCodec::LibSvtAv1 => "libsvtav1",
+            VideoCodec::LibAomAv1 => "libaom-av1",
+            VideoCodec::LibVpxVp9 => "libvpx-vp9",
+            VideoCodec::H264Nvenc => "h264_nvenc",
+            VideoCodec::HevcNvenc => "hevc_nvenc",
+            VideoCodec::H264Qsv => "h264_qsv",
+            VideoCodec::HevcQsv => "hevc_qsv",
+            VideoCodec::Vp9Qsv => "vp9_qsv",
+            VideoCodec::H264Vaapi => "h264_vaapi",
+            VideoCodec::HevcVaapi => "hevc_vaapi",
+            VideoCodec::Vp9Vaapi => "vp9_vaapi",
+            VideoCodec::H264VideoToolbox => "h264_videotoolbox",
+            VideoCodec::HevcVideoToolbox => "hevc_videotoolbox",
+            VideoCodec::ProresVideoToolbox => "prores_videotoolbox",
+            VideoCodec::H264Amf => "h264_amf",
+            VideoCodec::HevcAmf => "hevc_amf",
+        }
+    }
+
+    /// Codec name ffprobe reports for streams this encoder produces.
+    pub fn output_codec_name(&self) -> &'static str {
+        match self {
+            VideoCodec::Libx264
+            | VideoCodec::H264Nvenc
+            | VideoCodec::H264Qsv
+            | VideoCodec::H264Vaapi
+            | VideoCodec::H264VideoToolbox
+            | VideoCodec::H264Amf => "h264",
+            VideoCodec::Libx265
+            | VideoCodec::HevcNvenc
+            | VideoCodec::HevcQsv
+            | VideoCodec::HevcVaapi
+            | VideoCodec::HevcVideoToolbox
+            | VideoCodec::HevcAmf => "hevc",
+            VideoCodec::LibSvtAv1 | VideoCodec::LibAomAv1 => "av1",
+            VideoCodec::LibVpxVp9 | VideoCodec::Vp9Qsv | VideoCodec::Vp9Vaapi => "vp9",
+            VideoCodec::ProresVideoToolbox => "prores",
+        }
+    }
+
+    pub fn family(&self) -> EncoderFamily {
+        match self {
+            VideoCodec::Libx264 | VideoCodec::Libx265 => EncoderFamily::X26x,
+            VideoCodec::LibSvtAv1 => EncoderFamily::SvtAv1,
+            VideoCodec::LibAomAv1 => EncoderFamily::Aom,
+            VideoCodec::LibVpxVp9 => EncoderFamily::Vpx,
+            VideoCodec::H264Nvenc | VideoCodec::HevcNvenc => EncoderFamily::Nvenc,
+            VideoCodec::H264Qsv | VideoCodec::HevcQsv | VideoCodec::Vp9Qsv => EncoderFamily::Qsv,
+            VideoCodec::H264Vaapi | VideoCodec::HevcVaapi | VideoCodec::Vp9Vaapi => {
+                EncoderFamily::Vaapi
+            }
+            VideoCodec::H264VideoToolbox
+            | VideoCodec::HevcVideoToolbox
+            | VideoCodec::ProresVideoToolbox => EncoderFamily::VideoToolbox,
+            VideoCodec::H264Amf | VideoCodec::HevcAmf => EncoderFamily::Amf,
+        }
+    }
+
+    /// 10-bit HEVC and AV1 encoders that can carry HDR. Only libx265 and
+    /// libsvtav1 take the mastering display and content light levels, the
+    /// others get the colour tags.
+    pub fn supports_hdr10(&self) -> bool {
+        matches!(
+            self,
+            VideoCodec::Libx265
+                | VideoCodec::LibSvtAv1
+                | VideoCodec::LibAomAv1
+                | VideoCodec::HevcNvenc
+                | VideoCodec::HevcQsv
+        )
+    }
+}
+
+impl EncoderFamily {
+    /// Encoders that read ffmpeg's two-pass statistics (`-pass`, or
+    /// x265-params for libx265).
+    pub fn supports_two_pass(&self) -> bool {
+        matches!(
+            self,
+            EncoderFamily::X26x | EncoderFamily::Aom | EncoderFamily::Vpx
+        )
+    }
+
+    /// Encoders whose max bitrate, buffer size and constant bitrate flags
+    /// are mapped by the command builder.
+    pub fn supports_vbv(&self) -> bool {
+        matches!(
+            self,
+            EncoderFamily::X26x | EncoderFamily::Nvenc | EncoderFamily::Qsv | EncoderFamily::Vaapi
+        )
+    }
+
+    /// Whether the constant quality mode can be combined with a max bitrate.
+    /// QSV (ICQ) and VAAPI (CQP) ignore the cap.
+    pub fn supports_capped_quality(&self) -> bool {
+        matches!(self, EncoderFamily::X26x | EncoderFamily::Nvenc)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioCodec {
+    Aac,
+    Ac3,
+    Eac3,
+    Opus,
+    Flac,
+    Mp3,
+    Copy,
+}
+
+impl AudioCodec {
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Ac3 => "ac3",
+            AudioCodec::Eac3 => "eac3",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Flac => "flac",
+            AudioCodec::Mp3 => "libmp3lame",
+            AudioCodec::Copy => "copy",
+        }
+    }
+
+    /// Lossless and passthrough codecs ignore "-b:a".
+    pub fn takes_bitrate(&self) -> bool {
+        !matches!(self, AudioCodec::Copy | AudioCodec::Flac)
+    }
+
+    /// Most channels the encoder accepts, wider tracks are folded down.
+    pub fn max_channels(&self) -> u32 {
+        match self {
+            AudioCodec::Mp3 => 2,
+            AudioCodec::Ac3 | AudioCodec::Eac3 => 6,
+            AudioCodec::Aac | AudioCodec::Opus | AudioCodec::Flac => 8,
+            AudioCodec::Copy => u32::MAX,
+        }
+    }
+
+    /// Highest bitrate the format allows per track, in kb/s.
+    pub fn max_bitrate_kbps(&self) -> Option<f64> {
+        match self {
+            AudioCodec::Ac3 => Some(640.0),
+            AudioCodec::Mp3 => Some(320.0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioStrategy {
+    #[default]
+    FirstTrack,
+    CopyAll,
+    ConvertAll,
+}
+
+/// Channel layout of a converted audio track. Tracks are only ever folded
+/// down, a stereo source stays stereo with "5.1".
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelLayout {
+    #[default]
+    Keep,
+    #[serde(rename = "5.1")]
+    Surround51,
+    Stereo, // Centre and surrounds mixed into both sides
+}
+
+impl ChannelLayout {
+    pub fn max_channels(&self) -> u32 {
+        match self {
+            ChannelLayout::Keep => u32::MAX,
+            ChannelLayout::Surround51 => 6,
+            ChannelLayout::Stereo => 2,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleStrategy {
+    #[default]
+    Ignore,
+    CopyAll,
+    BurnIn,
+}
+
+/// What to do when the output file already exists.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingOutputPolicy {
+    #[default]
+    Overwrite,
+    Skip,
+    AutoSuffix, // "name (1).ext"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelMode {
+    #[default]
+    Abort, // Kill ffmpeg and delete the partial output
+    StopAndKeep, // Send "q" so ffmpeg finalizes a playable file up to the current point
+}
+
+/// How the video bitrate is controlled.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RateControl {
+    #[default]
+    Quality, // CRF or the encoder's constant quality, optionally capped by max_bitrate
+    ConstantBitrate, // video_bitrate held steady, for live and streaming targets
+}
+
+/// What to do with an HDR source. Unset picks Preserve when the encoder
+/// can write HDR10, ToneMap otherwise.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HdrMode {
+    ToneMap,  // Convert to 8-bit SDR BT.709
+    Preserve, // Keep PQ or HLG in a 10-bit encode with the HDR10 metadata
+}
+
+/// Rectangle kept by the crop filter, in source pixels.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Picture changes made before encoding. They run in the order deinterlace,
+/// crop, scale, pad, fps.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct VideoFilters {
+    pub deinterlace: bool,
+    pub crop: Option<CropRect>,
+    pub max_width: Option<u32>, // Downscale to fit, keeping the aspect ratio
+    pub max_height: Option<u32>,
+    pub pad_width: Option<u32>, // Centre the picture on a black frame of this size
+    pub pad_height: Option<u32>,
+    pub fps: Option<f64>,
+}
+
+impl VideoFilters {
+    pub fn is_empty(&self) -> bool {
+        *self == VideoFilters::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(crop) = self.crop {
+            if crop.width == 0 || crop.height == 0 {
+                return Err("The crop size must not be zero".to_string());
+            }
+        }
+        if [
+            self.max_width,
+            self.max_height,
+            self.pad_width,
+            self.pad_height,
+        ]
+        .contains(&Some(0))
+        {
+            return Err("Scale and pad sizes must not be zero".to_string());
+        }
+        if let Some(fps) = self.fps {
+            if !fps.is_finite() || fps <= 0.0 || fps > 1000.0 {
+                return Err(format!("Invalid frame rate {}", fps));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// EBU R128 loudness targets for the loudnorm filter.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct LoudnessTarget {
+    pub integrated: f64, // LUFS
+    pub true_peak: f64,  // dBTP
+    pub range: f64,      // LU
+}
+
+impl Default for LoudnessTarget {
+    // The EBU R128 broadcast values
+    fn default() -> Self {
+        Self {
+            integrated: -23.0,
+            true_peak: -1.0,
+            range: 11.0,
+        }
+    }
+}
+
+impl LoudnessTarget {
+    /// Ranges loudnorm accepts.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-70.0..=-5.0).contains(&self.integrated) {
+            return Err(format!(
+                "Integrated loudness {} LUFS is outside -70 to -5",
+                self.integrated
+            ));
+        }
+        if !(-9.0..=0.0).contains(&self.true_peak) {
+            return Err(format!(
+                "True peak {} dBTP is outside -9 to 0",
+                self.true_peak
+            ));
+        }
+        if !(1.0..=50.0).contains(&self.range) {
+            return Err(format!(
+                "Loudness range {} LU is outside 1 to 50",
+                self.range
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parses a bitrate such as "4000k", "4.5M" or "800000" into kb/s.
+pub fn parse_bitrate_kbps(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (number, scale) = if let Some(n) = value.strip_suffix(['k', 'K']) {
+        (n, 1.0)
+    } else if let Some(n) = value.strip_suffix(['m', 'M']) {
+        (n, 1000.0)
+    } else {
+        (value, 0.001)
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n > 0.0)
+        .map(|n| n * scale)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncoderPreset {
+    Ultrafast,
+    Superfast,
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+    // NVENC native presets
+    P1,
+    P2,
+    P3,
+    P4,
+    P5,
+    P6,
+    P7,
+}
+
+impl EncoderPreset {
+    pub fn is_nvenc_native(&self) -> bool {
+        matches!(
+            self,
+            EncoderPreset::P1
+                | EncoderPreset::P2
+                | EncoderPreset::P3
+                | EncoderPreset::P4
+                | EncoderPreset::P5
+                | EncoderPreset::P6
+                | EncoderPreset::P7
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EncoderPreset::Ultrafast => "ultrafast",
+            EncoderPreset::Superfast => "superfast",
+            EncoderPreset::Veryfast => "veryfast",
+            EncoderPreset::Faster => "faster",
+            EncoderPreset::Fast => "fast",
+            EncoderPreset::Medium => "medium",
+            EncoderPreset::Slow => "slow",
+            EncoderPreset::Slower => "slower",
+            EncoderPreset::Veryslow => "veryslow",
+            EncoderPreset::P1 => "p1",
+            EncoderPreset::P2 => "p2",
+            EncoderPreset::P3 => "p3",
+            EncoderPreset::P4 => "p4",
+            EncoderPreset::P5 => "p5",
+            EncoderPreset::P6 => "p6",
+            EncoderPreset::P7 => "p7",
+        }
+    }
+
+    /// Speed flag and value for `codec`. The x264 names are a speed scale
+    /// mapped onto each family's own setting; VAAPI and VideoToolbox have
+    /// none, so `Ok(None)`. The p1-p7 presets only exist for NVENC.
+    pub fn encoder_param(
+        &self,
+        codec: VideoCodec,
+    ) -> Result<Option<(&'static str, &'static str)>, String> {
+        use EncoderPreset::*;
+
+        let family = codec.family();
+        if self.is_nvenc_native() && family != EncoderFamily::Nvenc {
+            return Err(format!(
+                "Preset \"{}\" is only valid for NVENC encoders, not {}",
+                self.name(),
+                codec.ffmpeg_name()
+            ));
+        }
+
+        let param = match family {
+            EncoderFamily::X26x => Some(("-preset", self.name())),
+            EncoderFamily::Nvenc => Some((
+                "-preset",
+                match self {
+                    Ultrafast | P1 => "p1",
+                    Superfast | P2 => "p2",
+                    Veryfast | Faster | P3 => "p3",
+                    Fast | Medium | P4 => "p4",
+                    Slow | P5 => "p5",
+                    Slower | P6 => "p6",
+                    Veryslow | P7 => "p7",
+                },
+            )),
+            // 0 (slowest) to 13
+            EncoderFamily::SvtAv1 => Some((
+                "-preset",
+                match self {
+                    Ultrafast => "12",
+                    Superfast => "11",
+                    Veryfast => "10",
+                    Faster => "9",
+                    Fast => "8",
+                    Medium => "6",
+                    Slow => "4",
+                    Slower => "3",
+                    _ => "2",
+                },
+            )),
+            // libaom 0 (slowest) to 8, libvpx 0 to 5 in its default "good" mode
+            EncoderFamily::Aom => Some((
+                "-cpu-used",
+                match self {
+                    Ultrafast | Superfast => "8",
+                    Veryfast => "7",
+                    Faster => "6",
+                    Fast => "5",
+                    Medium => "4",
+                    Slow => "3",
+                    Slower => "2",
+                    _ => "1",
+                },
+            )),
+            EncoderFamily::Vpx => Some((
+                "-cpu-used",
+                match self {
+                    Ultrafast | Superfast | Veryfast | Faster => "5",
+                    Fast => "4",
+                    Medium => "3",
+                    Slow => "2",
+                    Slower => "1",
+                    _ => "0",
+                },
+            )),
+            EncoderFamily::Qsv => Some((
+                "-preset",
+                match self {
+                    Ultrafast | Superfast => "veryfast",
+                    other => other.name(),
+                },
+            )),
+            EncoderFamily::Amf => Some((
+                "-quality",
+                match self {
+                    Ultrafast | Superfast | Veryfast | Faster => "speed",
+                    Fast | Medium => "balanced",
+                    _ => "quality",
+                },
+            )),
+            EncoderFamily::Vaapi | EncoderFamily::VideoToolbox => None,
+        };
+        Ok(param)
+    }
+}
+
+/// Output container, derived from the output file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Mp4,
+    Mov,
+    Mkv,
+    Webm,
+    Avi,
+    Other,
+}
+
+impl Container {
+    pub fn from_path(path: &str) -> Self {
+        let extension = std::path::Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match extension.as_str() {
+            "mp4" | "m4v" => Container::Mp4,
+            "mov" => Container::Mov,
+            "mkv" => Container::Mkv,
+            "webm" => Container::Webm,
+            "avi" => Container::Avi,
+            _ => Container::Other,
+        }
+    }
+
+    /// Whether a video stream with this ffprobe codec name can be copied in
+    /// as is. Containers we know little about are given the benefit of the doubt.
+    pub fn can_hold_video(&self, codec_name: &str) -> bool {
+        match self {
+            Container::Mp4 => matches!(
+                codec_name,
+                "h264" | "hevc" | "av1" | "vp9" | "mpeg4" | "mpeg2video"
+            ),
+            Container::Mov => matches!(
+                codec_name,
+                "h264" | "hevc" | "prores" | "dnxhd" | "mjpeg" | "mpeg4" | "mpeg2video"
+            ),
+            Container::Webm => matches!(codec_name, "vp8" | "vp9" | "av1"),
+            Container::Mkv | Container::Avi | Container::Other => true,
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use crate::modules::conversion_types::CropRect;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+const SAMPLE_COUNT: usize = 8;
+const FRAMES_PER_SAMPLE: u32 = 24;
+// Brightest value still counted as black, for 8-bit video
+const BLACK_LIMIT: u32 = 24;
+// Rectangles this close, in pixels per edge, count as the same crop
+const EDGE_TOLERANCE: u32 = 4;
+// Below this share of agreeing samples the crop is not applied automatically
+pub const MIN_AUTO_CROP_CONFIDENCE: f64 = 0.5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CropDetection {
+    pub crop: Option<CropRect>, // None when there are no black bars
+    pub confidence: f64,        // Share of samples that agree with the crop, 0-1
+    pub samples: usize,         // Samples that gave a usable rectangle
+}
+
+/// cropdetect compares against the raw sample values, so the limit grows
+/// with the bit depth.
+fn black_limit(bit_depth: u32) -> u32 {
+    BLACK_LIMIT << bit_depth.saturating_sub(8)
+}
+
+/// Runs cropdetect on a few frames starting at `seconds` and returns the
+/// rectangle covering all of them, or None if nothing usable came out, e.g.
+/// on an all-black scene.
+fn sample_crop(input_path: &str, seconds: f64, bit_depth: u32) -> Result<Option<CropRect>, String> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args([
+        "-hide_banner",
+        "-nostdin",
+        "-ss",
+        &format!("{:.3}", seconds),
+        "-i",
+        input_path,
+        "-map",
+        "0:v:0",
+        "-frames:v",
+        &FRAMES_PER_SAMPLE.to_string(),
+        // reset=0 grows the rectangle over the sample, round=2 keeps it even
+        "-vf",
+        &format!(
+            "cropdetect=limit={}:round=2:reset=0",
+            black_limit(bit_depth)
+        ),
+        "-f",
+        "null",
+        "-",
+    ]);
+
+    // Hide console window on Windows
+    #[cfg(windows)]
+    cmd.creation_flags(0x08000000);
+
+    let output = cmd
+        .output()
+        .map_err(|e| format!("Failed to execute ffmpeg: {}", e))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    Ok(last_crop(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// The rectangle of the last cropdetect line, the one covering the sample.
+fn last_crop(stderr: &str) -> Option<CropRect> {
+    stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.rsplit_once("crop=").map(|(_, crop)| crop))
+        .and_then(parse_crop)
+}
+
+/// Parses "w:h:x:y" as printed by cropdetect. A black frame gives a negative
+/// size, which is rejected.
+fn parse_crop(value: &str) -> Option<CropRect> {
+    let parts: Vec<i64> = value
+        .trim()
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [width, height, x, y] if width > 0 && height > 0 && x >= 0 && y >= 0 => Some(CropRect {
+            width: width as u32,
+            height: height as u32,
+            x: x as u32,
+            y: y as u32,
+        }),
+        _ => None,
+    }
+}
+
+fn close_to(a: &CropRect, b: &CropRect) -> bool {
+    let near = |p: u32, q: u32| p.abs_diff(q) <= EDGE_TOLERANCE;
+    near(a.x, b.x)
+        && near(a.y, b.y)
+        && near(a.x + a.width, b.x + b.width)
+        && near(a.y + a.height, b.y + b.height)
+}
+
+/// Picks the rectangle most samples agree with. On a tie the larger one
+/// wins, cutting too little is better than cutting into the picture.
+fn most_stable(rects: &[CropRect]) -> Option<(CropRect, usize)> {
+    rects
+        .iter()
+        .map(|rect| (*rect, rects.iter().filter(|r| close_to(rect, r)).count()))
+        .max_by_key(|(rect, votes)| (*votes, rect.width as u64 * rect.height as u64))
+}
+
+/// Combines the samples of a `width` x `height` video. The most stable
+/// rectangle is kept unless it is the full frame.
+fn aggregate(rects: &[CropRect], width: u32, height: u32) -> CropDetection {
+    let Some((rect, votes)) = most_stable(rects) else {
+        return CropDetection {
+            crop: None,
+            confidence: 0.0,
+            samples: 0,
+        };
+    };
+
+    let full_frame = CropRect {
+        width,
+        height,
+        x: 0,
+        y: 0,
+    };
+    CropDetection {
+        crop: (!close_to(&rect, &full_frame)).then_some(rect),
+        confidence: votes as f64 / rects.len() as f64,
+        samples: rects.len(),
+    }
+}
+
+/// Samples the video at evenly spaced points and returns the most stable
+/// crop rectangle with how many samples agree with it.
+pub fn detect_crop(
+    input_path: &str,
+    duration: f64,
+    width: u32,
+    height: u32,
+    bit_depth: u32,
+) -> Result<CropDetection, String> {
+    let points: Vec<f64> = if duration > 0.0 {
+        (1..=SAMPLE_COUNT)
+            .map(|i| duration * i as f64 / (SAMPLE_COUNT + 1) as f64)
+            .collect()
+    } else {
+        vec![0.0]
+    };
+
+    let mut rects = Vec::new();
+    for seconds in points {
+        if let Some(rect) = sample_crop(input_path, seconds, bit_depth)? {
+            rects.push(rect);
+        }
+    }
+
+    let detection = aggregate(&rects, width, height);
+    if detection.samples == 0 {
+        eprintln!("[Crop] No usable sample in {}", input_path);
+    } else {
+        let found = detection
+            .crop
+            .map(|r| format!("{}x{} at {},{}", r.width, r.height, r.x, r.y))
+            .unwrap_or_else(|| "full frame".to_string());
+        eprintln!(
+            "[Crop] {}: {} ({:.0}% of {} samples)",
+            input_path,
+            found,
+            detection.confidence * 100.0,
+            detection.samples
+        );
+    }
+
+    Ok(detection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Last lines of one sample of a 2.40:1 film in a 1080p frame
+    const SAMPLE: &str = "\
+[Parsed_cropdetect_0 @ 0x55f1d8c0a2c0] x1:0 x2:1919 y1:142 y2:937 w:1920 h:784 x:0 y:148 \
+pts:48048 t:2.002000 limit:0.094118 crop=1920:784:0:148
+[Parsed_cropdetect_0 @ 0x55f1d8c0a2c0] x1:0 x2:1919 y1:138 y2:941 w:1920 h:800 x:0 y:140 \
+pts:49049 t:2.043708 limit:0.094118 crop=1920:800:0:140
+[out#0/null @ 0x55f1d8c09f40] video:10kB audio:0kB subtitle:0kB other streams:0kB
+frame=   24 fps=0.0 q=-0.0 Lsize=N/A time=00:00:01.00 bitrate=N/A speed=4.12x";
+
+    fn rect(width: u32, height: u32, x: u32, y: u32) -> CropRect {
+        CropRect {
+            width,
+            height,
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn reads_the_last_rectangle() {
+        assert_eq!(last_crop(SAMPLE), Some(rect(1920, 800, 0, 140)));
+        // An all-black sample leaves an inverted rectangle
+        assert_eq!(
+            last_crop(
+                "[Parsed_cropdetect_0 @ 0x5603] x1:1919 x2:0 y1:1079 y2:0 w:-1904 h:-1064 \
+                 x:1912 y:1072 pts:1001 t:0.041708 limit:0.094118 crop=-1904:-1064:1912:1072"
+            ),
+            None
+        );
+        assert_eq!(last_crop("frame=   24 fps=0.0 q=-0.0 Lsize=N/A"), None);
+    }
+
+    #[test]
+    fn keeps_the_rectangle_most_samples_agree_on() {
+        let letterbox = last_crop(SAMPLE).unwrap();
+        let rects = [
+            letterbox,
+            rect(1920, 796, 0, 142),
+            rect(1920, 1080, 0, 0), // Bright scene filling the frame
+            rect(1920, 800, 0, 140),
+            rect(1440, 800, 240, 140), // Dark edges in a night scene
+            rect(1920, 804, 0, 138),
+        ];
+        let detection = aggregate(&rects, 1920, 1080);
+        // The largest of the four that agree, cutting too little is safer
+        assert_eq!(detection.crop, Some(rect(1920, 804, 0, 138)));
+        assert_eq!(detection.samples, 6);
+        assert_eq!(detection.confidence, 4.0 / 6.0);
+    }
+
+    #[test]
+    fn full_frame_is_not_a_crop() {
+        let rects = [
+            rect(1920, 1080, 0, 0),
+            rect(1916, 1078, 2, 0),
+            rect(1920, 800, 0, 140),
+        ];
+        let detection = aggregate(&rects, 1920, 1080);
+        assert_eq!(detection.crop, None);
+        assert_eq!(detection.confidence, 2.0 / 3.0);
+
+        let nothing = aggregate(&[], 1920, 1080);
+        assert_eq!((nothing.crop, nothing.samples), (None, 0));
+    }
+
+    #[test]
+    fn ties_keep_the_larger_rectangle() {
+        let rects = [rect(1920, 800, 0, 140), rect(1920, 1040, 0, 20)];
+        assert_eq!(
+            aggregate(&rects, 1920, 1080).crop,
+            Some(rect(1920, 1040, 0, 20))
+        );
+    }
+
+    #[test]
+    fn black_limit_follows_the_bit_depth() {
+        assert_eq!(black_limit(8), 24);
+        assert_eq!(black_limit(10), 96);
+        assert_eq!(black_limit(12), 384);
+    }
+}
@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const STDERR_CAPACITY: usize = 64;
+const EXCERPT_LINES: usize = 12;
+
+/// Keeps the last lines ffmpeg wrote to stderr, which is where the reason
+/// for a failure ends up.
+pub struct StderrRing {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl StderrRing {
+    pub fn new() -> Self {
+        Self::with_extra(0)
+    }
+
+    /// Keeps `extra` lines on top of the usual tail, for output that is
+    /// read back once ffmpeg has exited.
+    pub fn with_extra(extra: usize) -> Self {
+        let capacity = STDERR_CAPACITY + extra;
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter()
+    }
+}
+
+impl Default for StderrRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorKind {
+    EncoderNotFound { encoder: Option<String> },
+    UnsupportedSubtitleCodec,
+    DiskFull,
+    PermissionDenied,
+    InvalidOption { option: Option<String> },
+    FileNotFound,
+    CorruptInput,
+    InvalidOutput, // ffmpeg succeeded but the result failed the sanity check
+    Cancelled,
+    Other,
+}
+
+/// Error reported to the frontend when a job fails.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConversionError {
+    #[serde(flatten)]
+    pub kind: ErrorKind,
+    pub message: String,
+    pub exit_code: Option<i32>,
+    pub log_excerpt: Vec<String>, // Relevant stderr lines, most recent last
+}
+
+impl ConversionError {
+    pub fn cancelled() -> Self {
+        Self {
+            kind: ErrorKind::Cancelled,
+            message: "Cancelled by user".to_string(),
+            exit_code: None,
+            log_excerpt: Vec::new(),
+        }
+    }
+
+    pub fn invalid_output(reason: &str) -> Self {
+        Self {
+            kind: ErrorKind::InvalidOutput,
+            message: format!("{}: {}", describe(&ErrorKind::InvalidOutput), reason),
+            exit_code: None,
+            log_excerpt: Vec::new(),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.kind == ErrorKind::Cancelled
+    }
+}
+
+impl From<String> for ConversionError {
+    fn from(message: String) -> Self {
+        Self {
+            kind: ErrorKind::Other,
+            message,
+            exit_code: None,
+            log_excerpt: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Text between the first pair of single quotes, e.g. the encoder name in
+/// "Unknown encoder 'libfoo'".
+fn quoted(line: &str) -> Option<String> {
+    let start = line.find('\'')? + 1;
+    let end = start + line[start..].find('\'')?;
+    Some(line[start..end].to_string())
+}
+
+fn classify_line(line: &str) -> Option<(u8, ErrorKind)> {
+    let lower = line.to_lowercase();
+
+    // Lower number wins when several lines match
+    if lower.contains("no space left on device") {
+        Some((0, ErrorKind::DiskFull))
+    } else if lower.contains("permission denied") {
+        Some((1, ErrorKind::PermissionDenied))
+    } else if lower.contains("unknown encoder")
+        || lower.contains("encoder not found")
+        || lower.contains("no nvenc capable devices found")
+        || lower.contains("cannot load libcuda")
+        || lower.contains("cannot load nvcuda")
+        || lower.contains("failed to initialise vaapi")
+    {
+        Some((
+            2,
+            ErrorKind::EncoderNotFound {
+                encoder: quoted(line),
+            },
+        ))
+    } else if lower.contains("subtitle encoding currently only possible")
+        || (lower.contains("subtitle") && lower.contains("not supported"))
+        || (lower.contains("could not find tag for codec") && lower.contains("subtitle"))
+    {
+        Some((3, ErrorKind::UnsupportedSubtitleCodec))
+    } else if lower.contains("unrecognized option")
+        || lower.contains("option not found")
+        || lower.contains("error setting option")
+        || lower.contains("invalid option")
+    {
+        Some((
+            4,
+            ErrorKind::InvalidOption {
+                option: quoted(line),
+            },
+        ))
+    } else if lower.contains("no such file or directory") {
+        Some((5, ErrorKind::FileNotFound))
+    } else if lower.contains("moov atom not found")
+        || lower.contains("ebml header parsing failed")
+        // Opening the input failed. The same reason after "Error while
+        // decoding" is a damaged frame that ffmpeg skips and carries on.
+        || (lower.trim_end().ends_with(": invalid data found when processing input")
+            && !lower.contains("error while decoding"))
+    {
+        Some((6, ErrorKind::CorruptInput))
+    } else {
+        None
+    }
+}
+
+/// Whether the error names the encoder or option at fault.
+fn is_specific(kind: &ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::EncoderNotFound { encoder: Some(_) }
+            | ErrorKind::InvalidOption { option: Some(_) }
+    )
+}
+
+fn describe(kind: &ErrorKind) -> String {
+    match kind {
+        ErrorKind::EncoderNotFound { encoder: Some(e) } => {
+            format!(
+                "Encoder '{}' is not available in this ffmpeg build or on this hardware",
+                e
+            )
+        }
+        ErrorKind::EncoderNotFound { encoder: None } => {
+            "The selected encoder is not available in this ffmpeg build or on this hardware"
+                .to_string()
+        }
+        ErrorKind::UnsupportedSubtitleCodec => {
+            "A subtitle track cannot be stored in the chosen container".to_string()
+        }
+        ErrorKind::DiskFull => "The output disk is full".to_string(),
+        ErrorKind::PermissionDenied => {
+            "Permission denied while reading the input or writing the output".to_string()
+        }
+        ErrorKind::InvalidOption { option: Some(o) } => {
+            format!("ffmpeg rejected the option '{}'", o)
+        }
+        ErrorKind::InvalidOption { option: None } => {
+            "ffmpeg rejected one of the conversion options".to_string()
+        }
+        ErrorKind::FileNotFound => "A file or directory could not be found".to_string(),
+        ErrorKind::CorruptInput => "The input file is corrupt or unreadable".to_string(),
+        ErrorKind::InvalidOutput => "The converted file is not valid".to_string(),
+        ErrorKind::Cancelled => "Cancelled by user".to_string(),
+        ErrorKind::Other => "FFmpeg failed".to_string(),
+    }
+}
+
+/// Turns a failed run into a typed error, keeping the stderr lines that
+/// explain it plus the tail of the log.
+pub fn classify(stderr: &StderrRing, exit_code: Option<i32>) -> ConversionError {
+    let lines: Vec<&String> = stderr.lines().collect();
+
+    let mut best: Option<(u8, usize, ErrorKind)> = None;
+    for (position, line) in lines.iter().enumerate() {
+        if let Some((rank, kind)) = classify_line(line) {
+            // On a tie the later line wins, the fatal one comes last, unless
+            // only the earlier one names what failed
+            let better = match &best {
+                None => true,
+                Some((r, _, k)) => {
+                    rank < *r || (rank == *r && (is_specific(&kind) || !is_specific(k)))
+                }
+            };
+            if better {
+                best = Some((rank, position, kind));
+            }
+        }
+    }
+
+    let tail_start = lines.len().saturating_sub(EXCERPT_LINES);
+    let mut log_excerpt: Vec<String> = Vec::new();
+    if let Some((_, position, _)) = &best {
+        if *position < tail_start {
+            log_excerpt.push(lines[*position].clone());
+        }
+    }
+    log_excerpt.extend(lines[tail_start..].iter().map(|l| (*l).clone()));
+
+    let kind = best.map(|(_, _, kind)| kind).unwrap_or(ErrorKind::Other);
+    let mut message = describe(&kind);
+    if kind == ErrorKind::Other {
+        if let Some(code) = exit_code {
+            message = format!("FFmpeg exited with error code {}", code);
+        }
+    }
+
+    ConversionError {
+        kind,
+        message,
+        exit_code,
+        log_excerpt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(tail: &str, exit_code: Option<i32>) -> ConversionError {
+        let mut stderr = StderrRing::new();
+        for line in tail.lines() {
+            stderr.push(line.to_string());
+        }
+        classify(&stderr, exit_code)
+    }
+
+    fn kind(tail: &str) -> ErrorKind {
+        run(tail, Some(1)).kind
+    }
+
+    #[test]
+    fn classifies_missing_encoders() {
+        assert_eq!(
+            kind(
+                "Stream mapping:\n  \
+                  Stream #0:0 -> #0:0 (h264 (native) -> ? (?))\n\
+                  [vost#0:0 @ 0x55e4a1c0f980] Unknown encoder 'libsvtav1'\n\
+                  [vost#0:0 @ 0x55e4a1c0f980] Error selecting an encoder\n\
+                  Error opening output file out.mkv.\n\
+                  Error opening output files: Encoder not found"
+            ),
+            ErrorKind::EncoderNotFound {
+                encoder: Some("libsvtav1".to_string())
+            }
+        );
+        assert_eq!(
+            kind(
+                "[hevc_nvenc @ 0x5581c0a3c2c0] Cannot load libcuda.so.1\n\
+                  [hevc_nvenc @ 0x5581c0a3c2c0] Could not dynamically load CUDA\n\
+                  Error initializing output stream 0:0 -- Error while opening encoder \
+                  for output stream #0:0 - maybe incorrect parameters such as bit_rate, \
+                  rate, width or height\n\
+                  Conversion failed!"
+            ),
+            ErrorKind::EncoderNotFound { encoder: None }
+        );
+    }
+
+    #[test]
+    fn classifies_unsupported_subtitles() {
+        assert_eq!(
+            kind(
+                "[mp4 @ 0x55d5e0a3d440] Could not find tag for codec hdmv_pgs_subtitle \
+                  in stream #2, codec not currently supported in container\n\
+                  Could not write header for output file #0 (incorrect codec parameters ?): \
+                  Invalid argument\n\
+                  Error initializing output stream 0:2 --\n\
+                  Conversion failed!"
+            ),
+            ErrorKind::UnsupportedSubtitleCodec
+        );
+    }
+
+    #[test]
+    fn classifies_disk_and_permission_errors() {
+        assert_eq!(
+            kind(
+                "frame= 4520 fps= 88 q=28.0 size=  512000kB time=00:03:08.41 \
+                  bitrate=22261.4kbits/s speed=3.67x\n\
+                  av_interleaved_write_frame(): No space left on device\n\
+                  [mp4 @ 0x5612f4c3a0c0] Error writing trailer of out.mp4: \
+                  No space left on device\n\
+                  Conversion failed!"
+            ),
+            ErrorKind::DiskFull
+        );
+        assert_eq!(
+            kind(
+                "[out#0/mp4 @ 0x55c1f2e3b4c0] Error opening output /mnt/share/out.mp4: \
+                  Permission denied\n\
+                  Error opening output file /mnt/share/out.mp4.\n\
+                  Error opening output files: Permission denied"
+            ),
+            ErrorKind::PermissionDenied
+        );
+    }
+
+    #[test]
+    fn classifies_invalid_options_by_name() {
+        assert_eq!(
+            kind(
+                "Unrecognized option 'x265-params'.\n\
+                  Error splitting the argument list: Option not found"
+            ),
+            ErrorKind::InvalidOption {
+                option: Some("x265-params".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_missing_and_corrupt_inputs() {
+        assert_eq!(
+            kind("/videos/missing.mkv: No such file or directory"),
+            ErrorKind::FileNotFound
+        );
+        assert_eq!(
+            kind(
+                "[matroska,webm @ 0x5620a8c3e300] EBML header parsing failed\n\
+                  [in#0 @ 0x5620a8c3e100] Error opening input: \
+                  Invalid data found when processing input\n\
+                  Error opening input file /videos/broken.mkv.\n\
+                  Error opening input files: Invalid data found when processing input"
+            ),
+            ErrorKind::CorruptInput
+        );
+        assert_eq!(
+            kind(
+                "[mov,mp4,m4a,3gp,3g2,mj2 @ 0x55f0b2a1c840] moov atom not found\n\
+                  /videos/partial.mp4: Invalid data found when processing input"
+            ),
+            ErrorKind::CorruptInput
+        );
+    }
+
+    #[test]
+    fn decoding_warnings_are_not_fatal() {
+        let error = run(
+            "[h264 @ 0x55b7c8e4f600] error while decoding MB 45 12, bytestream -7\n\
+             [h264 @ 0x55b7c8e4f600] concealing 1620 DC, 1620 AC, 1620 MV errors in P frame\n\
+             [mpegts @ 0x55b7c8e2a040] Packet corrupt (stream = 0, dts = 183600).\n\
+             [vist#0:0/h264 @ 0x55b7c8e4e9c0] Error while decoding stream #0:0: \
+             Invalid data found when processing input\n\
+             [libx264 @ 0x55b7c8e51280] frame I:12 Avg QP:20.14 size: 98432\n\
+             Conversion failed!",
+            Some(187),
+        );
+        assert_eq!(error.kind, ErrorKind::Other);
+        assert_eq!(error.message, "FFmpeg exited with error code 187");
+    }
+
+    #[test]
+    fn keeps_the_matched_line_in_the_excerpt() {
+        let mut tail = vec!["in.mkv: Permission denied".to_string()];
+        tail.extend((0..20).map(|n| format!("line {}", n)));
+        let error = run(&tail.join("\n"), Some(1));
+        assert_eq!(error.log_excerpt.len(), EXCERPT_LINES + 1);
+        assert_eq!(error.log_excerpt[0], "in.mkv: Permission denied");
+        assert_eq!(error.log_excerpt.last().unwrap(), "line 19");
+    }
+}
@@ -1,16 +1,47 @@
+use crate::modules::command_builder::{build_command, CommandPlan};
+use crate::modules::conversion_types::{
+    parse_bitrate_kbps, AudioCodec, AudioStrategy, CancelMode, ChannelLayout, EncoderFamily,
+    EncoderPreset, ExistingOutputPolicy, HdrMode, LoudnessTarget, RateControl, SubtitleStrategy,
+    VideoCodec, VideoFilters,
+};
+use crate::modules::crop_detect::{self, CropDetection, MIN_AUTO_CROP_CONFIDENCE};
+use crate::modules::ffmpeg_error::{classify, ConversionError, StderrRing};
+use crate::modules::history::{self, HistoryEntry};
+use crate::modules::job_log::{unix_now, JobLog};
+use crate::modules::job_queue::{JobQueue, JobStatus};
+use crate::modules::loudness::{self, LoudnessMeasurement};
+use crate::modules::metadata_extractor::{extract_metadata, HdrFormat, VideoMetadata};
+use crate::modules::output_file::{self, OutputTarget, PassLogDir};
+use crate::modules::preflight::{self, PreflightAction, PreflightDecision};
+use crate::modules::progress::{
+    format_hms, ActiveClock, PassSpan, ProgressEstimator, ProgressParser,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::{Arc, Mutex};
-use tauri::{Emitter, Manager, State, Window};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
 use tokio::sync::Mutex as TokioMutex;
 use tokio::task;
 
 #[cfg(windows)]
 use std::os::windows::process::CommandExt;
 
+// How long ffmpeg gets to finalize the output after "q" before it is killed
+const GRACEFUL_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+// How often a job paused between two ffmpeg runs checks whether it was resumed
+const SUSPENDED_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+// Prefix of the two-pass statistics files, inside the job's PassLogDir
+const PASSLOG_NAME: &str = "ffmpeg2pass";
+
+// Cost of a loudness measurement pass relative to an encode pass, it only decodes the audio
+const LOUDNESS_PASS_COST: f64 = 0.1;
+
 #[derive(Clone, Serialize, Debug)]
 pub struct ConversionProgress {
     pub id: String,
@@ -22,6 +53,33 @@ pub struct ConversionProgress {
     pub speed: String,
     pub progress: f64,                  // 0.0 to 100.0
     pub time_remaining: Option<String>, // Estimated time remaining (HH:MM:SS)
+    pub paused: bool,                   // Process suspended, ETA frozen
+}
+
+/// Summary of a finished conversion, sent with the "conversion_completed" event.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConversionReport {
+    pub id: String,
+    pub output_path: String,
+    pub input_size: u64,        // Bytes
+    pub output_size: u64,       // Bytes
+    pub compression_ratio: f64, // output_size / input_size
+    pub average_fps: f64,
+    pub wall_time_seconds: f64, // From probing the input to the output being in place
+    pub bitrate_kbps: Option<f64>, // Overall output bitrate, None without a duration
+    pub command: String,
+    #[serde(default)]
+    pub loudness: Vec<LoudnessMeasurement>, // Input loudness of the normalized tracks
+}
+
+/// Sent with the "conversion_cancelled" event once a cancelled ffmpeg process has exited.
+#[derive(Clone, Serialize, Debug)]
+pub struct CancellationResult {
+    pub id: String,
+    pub mode: CancelMode,
+    pub output_path: String,
+    pub output_kept: bool,
+    pub message: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -29,209 +87,944 @@ pub struct ConversionOptions {
     pub id: String,
     pub input_path: String,
     pub output_path: String,
-    pub video_codec: String,
+    pub video_codec: VideoCodec,
+    pub remux: Option<bool>, // Copy the video stream, video_codec and its settings are unused
+    pub target_size_mb: Option<f64>, // Two-pass encode to this size, replaces the CRF
+    pub rate_control: Option<RateControl>, // Defaults to quality (CRF)
+    pub video_bitrate: Option<String>, // "4000k" or "4M", constant bitrate mode only
+    pub max_bitrate: Option<String>, // VBV cap on top of the CRF
+    pub buffer_size: Option<String>, // VBV buffer, defaults from the bitrate
+    pub filters: Option<VideoFilters>,
+    pub hdr_mode: Option<HdrMode>,        // Only used for HDR sources
+    pub loudness: Option<LoudnessTarget>, // Normalize the converted audio, off when unset
     pub audio_track_index: Option<u32>,
     pub subtitle_track_index: Option<u32>,
     pub duration_seconds: f64,
     pub total_frames: Option<u64>, // Total number of frames in the video
     // New strategies
-    pub audio_strategy: Option<String>, // "copy_all", "convert_all", "first_track"
-    pub subtitle_strategy: Option<String>, // "copy_all", "burn_in", "ignore"
-    pub audio_codec: Option<String>,    // "aac", "ac3", "copy"
-    pub audio_bitrate: Option<String>,  // "128k"
+    pub audio_strategy: Option<AudioStrategy>,
+    pub subtitle_strategy: Option<SubtitleStrategy>,
+    pub audio_codec: Option<AudioCodec>,
+    pub audio_bitrate: Option<String>, // "128k" for stereo, scaled by the channel count
+    pub channel_layout: Option<ChannelLayout>, // Layout of every converted track, keep when unset
+    pub track_channel_layouts: Option<HashMap<u32, ChannelLayout>>, // By input stream index
+    pub stereo_compat_track: Option<bool>, // Add a stereo track after each surround one
     // Advanced Video Options
     pub crf: Option<u8>,
-    pub preset: Option<String>,
+    pub preset: Option<EncoderPreset>,
     pub profile: Option<String>,
     pub tune: Option<String>,
+    pub existing_output: Option<ExistingOutputPolicy>, // Defaults to overwrite
+    pub preflight: Option<bool>,                       // Apply the preflight rules, on by default
+    pub auto_crop: Option<bool>, // Detect and remove black bars, off by default
+}
+
+impl ConversionOptions {
+    /// Checks combinations serde cannot catch on its own. Called before a
+    /// job is accepted into the queue.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.input_path.trim().is_empty() || self.output_path.trim().is_empty() {
+            return Err("Input and output paths are required".to_string());
+        }
+
+        if self.input_path == self.output_path {
+            return Err(format!(
+                "Output path must differ from the input path ({})",
+                self.output_path
+            ));
+        }
+
+        if let Some(crf) = self.crf {
+            let max_crf = match self.video_codec.family() {
+                EncoderFamily::SvtAv1 | EncoderFamily::Aom | EncoderFamily::Vpx => 63,
+                _ => 51,
+            };
+            if crf > max_crf {
+                return Err(format!(
+                    "CRF {} is out of range for {} (0-{})",
+                    crf,
+                    self.video_codec.ffmpeg_name(),
+                    max_crf
+                ));
+            }
+        }
+
+        if self.remux.unwrap_or(false) && self.subtitle_strategy == Some(SubtitleStrategy::BurnIn) {
+            return Err("Subtitles cannot be burned in when remuxing".to_string());
+        }
+
+        if let Some(target_size_mb) = self.target_size_mb {
+            if target_size_mb.is_nan() || target_size_mb <= 0.0 {
+                return Err(format!("Invalid target size {} MB", target_size_mb));
+            }
+            if self.remux.unwrap_or(false) {
+                return Err("A target size needs the video to be encoded, not remuxed".to_string());
+            }
+            if !self.video_codec.family().supports_two_pass() {
+                return Err(format!(
+                    "A target size needs a two-pass encoder (libx264, libx265, libaom-av1 or libvpx-vp9), not {}",
+                    self.video_codec.ffmpeg_name()
+                ));
+            }
+        }
+
+        self.validate_rate_control()?;
+
+        if let Some(filters) = &self.filters {
+            filters.validate()?;
+            if self.remux.unwrap_or(false) && !filters.is_empty() {
+                return Err("Video filters need the video to be encoded, not remuxed".to_string());
+            }
+        }
+
+        if let Some(preset) = self.preset {
+            preset.encoder_param(self.video_codec)?;
+        }
+
+        if let Some(target) = &self.loudness {
+            target.validate()?;
+            if self.audio_strategy == Some(AudioStrategy::CopyAll)
+                || self.audio_codec == Some(AudioCodec::Copy)
+            {
+                return Err(
+                    "Loudness normalization needs the audio to be converted, not copied"
+                        .to_string(),
+                );
+            }
+        }
+
+        let changes_layout = self
+            .channel_layout
+            .is_some_and(|l| l != ChannelLayout::Keep)
+            || self
+                .track_channel_layouts
+                .as_ref()
+                .is_some_and(|layouts| layouts.values().any(|l| *l != ChannelLayout::Keep));
+        if changes_layout
+            && (self.audio_strategy == Some(AudioStrategy::CopyAll)
+                || self.audio_codec == Some(AudioCodec::Copy))
+        {
+            return Err(
+                "Changing the channel layout needs the audio to be converted, not copied"
+                    .to_string(),
+            );
+        }
+
+        if let Some(bitrate) = &self.audio_bitrate {
+            if parse_bitrate_kbps(bitrate).is_none() {
+                return Err(format!(
+                    "Invalid audio bitrate \"{}\", expected a value like \"128k\"",
+                    bitrate
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_rate_control(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("video bitrate", &self.video_bitrate),
+            ("max bitrate", &self.max_bitrate),
+            ("buffer size", &self.buffer_size),
+        ] {
+            if let Some(value) = value {
+                if parse_bitrate_kbps(value).is_none() {
+                    return Err(format!(
+                        "Invalid {} \"{}\", expected a value like \"4000k\" or \"4M\"",
+                        name, value
+                    ));
+                }
+            }
+        }
+
+        let rate_control = self.rate_control.unwrap_or_default();
+        let uses_vbv = rate_control == RateControl::ConstantBitrate || self.max_bitrate.is_some();
+        if !uses_vbv {
+            if self.video_bitrate.is_some() {
+                return Err("A video bitrate needs the constant bitrate mode".to_string());
+            }
+            if self.buffer_size.is_some() {
+                return Err(
+                    "A buffer size needs a max bitrate or the constant bitrate mode".to_string(),
+                );
+            }
+            return Ok(());
+        }
+
+        let family = self.video_codec.family();
+        if self.remux.unwrap_or(false) {
+            return Err("Bitrate settings do not apply when remuxing".to_string());
+        }
+        if !family.supports_vbv() {
+            return Err(format!(
+                "Bitrate limits are only supported with libx264, libx265, NVENC, QSV and VAAPI, not {}",
+                self.video_codec.ffmpeg_name()
+            ));
+        }
+
+        match rate_control {
+            RateControl::ConstantBitrate => {
+                if self.video_bitrate.is_none() {
+                    return Err("The constant bitrate mode needs a video bitrate".to_string());
+                }
+                if self.max_bitrate.is_some() {
+                    return Err("A max bitrate has no effect in constant bitrate mode".to_string());
+                }
+                if self.target_size_mb.is_some() {
+                    return Err(
+                        "A target size sets its own bitrate, it cannot be combined with constant bitrate"
+                            .to_string(),
+                    );
+                }
+            }
+            RateControl::Quality => {
+                if self.target_size_mb.is_none() && !family.supports_capped_quality() {
+                    return Err(format!(
+                        "{} cannot cap its constant quality mode, use constant bitrate instead",
+                        self.video_codec.ffmpeg_name()
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the options ask for more than a remux can give: a bitrate,
+    /// filters, cropping, tone mapping or burnt-in subtitles. The CRF and
+    /// preset are left out, every job carries them.
+    pub fn changes_video(&self, metadata: &VideoMetadata) -> bool {
+        self.target_size_mb.is_some()
+            || self.rate_control == Some(RateControl::ConstantBitrate)
+            || self.video_bitrate.is_some()
+            || self.max_bitrate.is_some()
+            || self.filters.as_ref().is_some_and(|f| !f.is_empty())
+            || self.auto_crop.unwrap_or(false)
+            || (self.hdr_mode == Some(HdrMode::ToneMap)
+                && metadata.color.base_layer_hdr().is_some())
+            || self.subtitle_strategy == Some(SubtitleStrategy::BurnIn)
+    }
+}
+
+/// A spawned ffmpeg process and the state its progress reader shares with
+/// the pause, resume and cancel commands.
+#[derive(Clone)]
+pub struct ActiveProcess {
+    pub child: Arc<TokioMutex<tokio::process::Child>>,
+    pub pid: Option<u32>,
+    pub clock: Arc<Mutex<ActiveClock>>,
+    pub last_progress: Arc<Mutex<Option<ConversionProgress>>>,
+    pub stdin: Arc<TokioMutex<Option<ChildStdin>>>,
+    pub cancel_mode: Arc<Mutex<Option<CancelMode>>>, // Set once the job is cancelled
 }
 
 pub struct ConversionManager {
-    // Store Arc<TokioMutex<Child>> to allow killing from other commands
-    pub processes: Arc<Mutex<HashMap<String, Arc<TokioMutex<tokio::process::Child>>>>>,
+    // Store the child behind Arc<TokioMutex> to allow killing from other commands
+    pub processes: Arc<Mutex<HashMap<String, ActiveProcess>>>,
+    // Ordered jobs, owned by the backend so they survive webview reloads
+    pub queue: Arc<Mutex<JobQueue>>,
 }
 
 impl ConversionManager {
     pub fn new() -> Self {
         Self {
             processes: Arc::new(Mutex::new(HashMap::new())),
+            queue: Arc::new(Mutex::new(JobQueue::new())),
         }
     }
 }
 
-pub async fn convert_video(
-    window: Window,
-    options: ConversionOptions,
-    state: State<'_, ConversionManager>,
-) -> Result<(), String> {
-    let mut args = vec![
-        "-i".to_string(),
-        options.input_path.clone(),
-        "-c:v".to_string(),
-        options.video_codec.clone(),
-    ];
-
-    // Apply Preset (convert software presets to hardware presets if needed)
-    let preset = options.preset.clone().unwrap_or_else(|| {
-        if options.video_codec.contains("nvenc") {
-            "p4".to_string() // Default NVENC preset (balanced)
-        } else {
-            "fast".to_string() // Default software preset
+fn queue_file_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join("queue.json"))
+}
+
+pub fn history_file_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join("history.jsonl"))
+}
+
+pub fn preflight_rules_file_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join("preflight_rules.json"))
+}
+
+fn load_preflight_rules(app: &AppHandle) -> Vec<preflight::PreflightRule> {
+    match preflight_rules_file_path(app) {
+        Some(path) => preflight::load_rules(&path),
+        None => preflight::default_rules(),
+    }
+}
+
+/// Probes a file and tells what the preflight rules would do with it.
+pub async fn evaluate_preflight(
+    app: &AppHandle,
+    input_path: String,
+) -> Result<PreflightDecision, String> {
+    let rules = load_preflight_rules(app);
+    let probe_path = input_path.clone();
+    let metadata = task::spawn_blocking(move || extract_metadata(&probe_path))
+        .await
+        .map_err(|e| format!("Failed to probe input: {}", e))??;
+    Ok(preflight::evaluate(&rules, &metadata, &input_path))
+}
+
+/// Probes a file and looks for black bars to crop.
+pub async fn detect_crop(input_path: String) -> Result<CropDetection, String> {
+    task::spawn_blocking(move || {
+        let metadata = extract_metadata(&input_path)?;
+        crop_detect::detect_crop(
+            &input_path,
+            metadata.duration,
+            metadata.width,
+            metadata.height,
+            metadata.color.bit_depth(),
+        )
+    })
+    .await
+    .map_err(|e| format!("Failed to detect crop: {}", e))?
+}
+
+pub fn job_log_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_log_dir().ok().map(|dir| dir.join("jobs"))
+}
+
+/// Loads the queue saved by a previous run. Called once from `setup`.
+pub fn restore_queue(app: &AppHandle) {
+    let Some(path) = queue_file_path(app) else {
+        return;
+    };
+    if !path.exists() {
+        return;
+    }
+
+    match JobQueue::load(&path) {
+        Ok(restored) => {
+            let state = app.state::<ConversionManager>();
+            *state.queue.lock().unwrap() = restored;
+            eprintln!("[Queue] Restored queue from {}", path.display());
         }
-    });
+        Err(e) => eprintln!("[Queue] {}", e),
+    }
 
-    // Convert software preset names to NVENC presets if using NVENC
-    let final_preset = if options.video_codec.contains("nvenc") {
-        match preset.as_str() {
-            "ultrafast" => "p1",
-            "superfast" => "p2",
-            "veryfast" => "p3",
-            "faster" => "p3",
-            "fast" => "p4",
-            "medium" => "p4",
-            "slow" => "p5",
-            "slower" => "p6",
-            "veryslow" => "p7",
-            _ => {
-                // If already a p1-p7 preset, use as-is, otherwise default to p4
-                if preset.starts_with('p') && preset.len() == 2 {
-                    preset.as_str()
-                } else {
-                    "p4"
+    emit_queue_updated(app);
+    process_queue(app);
+}
+
+pub fn persist_queue(app: &AppHandle) {
+    if let Some(path) = queue_file_path(app) {
+        let state = app.state::<ConversionManager>();
+        let result = state.queue.lock().unwrap().save(&path);
+        if let Err(e) = result {
+            eprintln!("[Queue] {}", e);
+        }
+    }
+}
+
+/// Notifies the frontend and saves the queue. Called after every state change.
+pub fn emit_queue_updated(app: &AppHandle) {
+    persist_queue(app);
+    let state = app.state::<ConversionManager>();
+    let snapshot = state.queue.lock().unwrap().snapshot();
+    let _ = app.emit("queue_updated", snapshot);
+}
+
+pub fn enqueue(app: &AppHandle, options: ConversionOptions) -> Result<(), String> {
+    options.validate()?;
+    {
+        let state = app.state::<ConversionManager>();
+        state.queue.lock().unwrap().enqueue(options)?;
+    }
+    emit_queue_updated(app);
+    process_queue(app);
+    Ok(())
+}
+
+/// Starts queued jobs until the parallel limit is reached. Every finished job
+/// runs the scheduler again, so the queue drains even with the window hidden.
+pub fn process_queue(app: &AppHandle) {
+    let state = app.state::<ConversionManager>();
+    loop {
+        let next = state.queue.lock().unwrap().start_next();
+        match next {
+            Some(options) => {
+                emit_queue_updated(app);
+                let app_handle = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    run_job(app_handle, options).await;
+                });
+            }
+            None => break,
+        }
+    }
+}
+
+async fn run_job(app: AppHandle, options: ConversionOptions) {
+    let id = options.id.clone();
+    let started_at = unix_now();
+    let result = convert_video(app.clone(), options, app.state::<ConversionManager>()).await;
+
+    let idle = {
+        let state = app.state::<ConversionManager>();
+        let mut queue = state.queue.lock().unwrap();
+        // Cancelled jobs were already marked by cancel_conversion
+        if queue.get(&id).map(|j| j.status.has_process()) == Some(true) {
+            let has_warnings = queue.get(&id).map(|j| !j.warnings.is_empty()) == Some(true);
+            if let Ok(Some(report)) = &result {
+                queue.set_report(&id, report.clone());
+            }
+            match result {
+                Ok(_) if has_warnings => {
+                    queue.set_status(&id, JobStatus::CompletedWithWarnings, None)
                 }
+                Ok(_) => queue.set_status(&id, JobStatus::Completed, None),
+                Err(e) => queue.set_status(&id, JobStatus::Failed, Some(e)),
+            }
+        } else if let Err(e) = result {
+            if e.is_cancelled() {
+                queue.set_status(&id, JobStatus::Cancelled, Some(e));
             }
         }
-    } else {
-        preset.as_str()
+        queue.running_count() == 0
     };
 
-    args.push("-preset".to_string());
-    args.push(final_preset.to_string());
+    record_history(&app, &id, started_at);
+
+    if idle {
+        // Reset Tray Status
+        crate::update_tray_status(&app, "No active conversions");
+        crate::set_stop_enabled(&app, false);
+    }
+    refresh_tray_pause_items(&app);
+
+    emit_queue_updated(&app);
+    process_queue(&app);
+}
+
+fn record_history(app: &AppHandle, id: &str, started_at: u64) {
+    let Some(path) = history_file_path(app) else {
+        return;
+    };
+    let job = {
+        let state = app.state::<ConversionManager>();
+        let queue = state.queue.lock().unwrap();
+        queue.get(id).cloned()
+    };
 
-    // Apply CRF (default to 23 if not specified, only if not using hardware accel which might use different flags, but let's try generic first)
-    // Note: NVENC uses -cq for VBR, but -crf is ignored or error?
-    // Actually, for simplicity, we'll assume software encoding or that the user knows what they are doing.
-    // But better: if encoder is libx264/libx265, use -crf.
-    if options.video_codec.contains("libx26")
-        || options.video_codec == "libx264"
-        || options.video_codec == "libx265"
+    // Jobs interrupted by quitting are not finished yet
+    if let Some(job) = job.filter(|j| j.status.is_finished() && j.status != JobStatus::Interrupted)
     {
-        if let Some(crf) = options.crf {
-            args.push("-crf".to_string());
-            args.push(crf.to_string());
+        let entry = HistoryEntry::from_job(&job, started_at, unix_now());
+        if let Err(e) = history::append(&path, &entry) {
+            eprintln!("[History] {}", e);
         }
-    } else if options.video_codec.contains("nvenc") {
-        // For NVENC, use -cq if provided, otherwise default logic
-        if let Some(crf) = options.crf {
-            args.push("-cq".to_string());
-            args.push(crf.to_string());
+    }
+}
+
+/// Queues a past job again with the same options, under a new job id.
+pub fn rerun_history_entry(app: &AppHandle, entry_id: &str) -> Result<String, String> {
+    let path = history_file_path(app).ok_or_else(|| "History unavailable".to_string())?;
+    let entry = history::find(&path, entry_id)?;
+
+    let mut options = entry.options;
+    // Re-running a re-run keeps a single suffix
+    let base_id = entry
+        .job_id
+        .split("-rerun-")
+        .next()
+        .unwrap_or(&entry.job_id);
+    options.id = format!("{}-rerun-{}", base_id, unix_now());
+    let id = options.id.clone();
+    enqueue(app, options)?;
+    Ok(id)
+}
+
+/// Runs one job to completion. Returns `None` when the job was skipped,
+/// because its output already exists or a preflight rule said so.
+pub async fn convert_video(
+    app: AppHandle,
+    options: ConversionOptions,
+    state: State<'_, ConversionManager>,
+) -> Result<Option<ConversionReport>, ConversionError> {
+    let started = std::time::Instant::now();
+    let mut log = JobLog::create(job_log_dir(&app).as_deref(), &options.id);
+    log.line(&format!("Input: {}", options.input_path));
+    log.line(&format!("Output: {}", options.output_path));
+
+    let policy = options.existing_output.unwrap_or_default();
+    let target = match output_file::resolve_output(&options.output_path, &options.id, policy) {
+        Ok(Some(target)) => target,
+        Ok(None) => {
+            let warning = format!("Skipped, {} already exists", options.output_path);
+            eprintln!("[FFmpeg] {}", warning);
+            log.line(&warning);
+            state
+                .queue
+                .lock()
+                .unwrap()
+                .add_warning(&options.id, warning);
+            return Ok(None);
+        }
+        Err(e) => {
+            log.line(&e);
+            return Err(e.into());
         }
+    };
+
+    let destination = target.destination.to_string_lossy().to_string();
+    if destination != options.output_path {
+        log.line(&format!("Output exists, writing to {}", destination));
+        state
+            .queue
+            .lock()
+            .unwrap()
+            .set_resolved_output(&options.id, destination);
+        emit_queue_updated(&app);
     }
+    log.line(&format!("Temporary file: {}", target.temp.display()));
+
+    let input_path = options.input_path.clone();
+    let probe = task::spawn_blocking(move || extract_metadata(&input_path))
+        .await
+        .map_err(|e| format!("Failed to probe input: {}", e))
+        .and_then(|result| result);
+    let metadata = match probe {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            log.line(&format!("Probe failed: {}", e));
+            return Err(e.into());
+        }
+    };
 
-    // Apply Profile
-    if let Some(profile) = &options.profile {
-        args.push("-profile:v".to_string());
-        args.push(profile.clone());
+    log.section("ffprobe");
+    log.line(&serde_json::to_string_pretty(&metadata).unwrap_or_default());
+
+    let decision = if options.preflight.unwrap_or(true) {
+        let decision =
+            preflight::evaluate(&load_preflight_rules(&app), &metadata, &options.input_path);
+        eprintln!("[Preflight] {}: {}", options.id, decision.explanation);
+        log.section("preflight");
+        log.line(&decision.explanation);
+        state
+            .queue
+            .lock()
+            .unwrap()
+            .set_preflight(&options.id, decision.clone());
+        emit_queue_updated(&app);
+        Some(decision)
+    } else {
+        None
+    };
+
+    if let Some(decision) = decision
+        .as_ref()
+        .filter(|d| d.action == PreflightAction::Skip)
+    {
+        state
+            .queue
+            .lock()
+            .unwrap()
+            .add_warning(&options.id, format!("Skipped. {}", decision.explanation));
+        return Ok(None);
     }
 
-    // Apply Tune
-    if let Some(tune) = &options.tune {
-        args.push("-tune".to_string());
-        args.push(tune.clone());
+    // Encode into the temporary file, it replaces the destination only once checked
+    let mut encode_options = options.clone();
+    encode_options.output_path = target.temp.to_string_lossy().to_string();
+
+    let mut remux_by_rule = decision.map(|d| d.action) == Some(PreflightAction::RemuxOnly)
+        && !options.remux.unwrap_or(false);
+    if remux_by_rule && options.changes_video(&metadata) {
+        // A remux would silently drop what was asked for
+        let warning = "Remux rule ignored, the options need an encode".to_string();
+        eprintln!("[Preflight] {}", warning);
+        log.line(&warning);
+        state
+            .queue
+            .lock()
+            .unwrap()
+            .add_warning(&options.id, warning);
+        remux_by_rule = false;
+    }
+    if remux_by_rule {
+        encode_options.remux = Some(true);
+    }
+
+    // A crop needs an encode, and one set by hand wins over detection
+    let has_crop = encode_options
+        .filters
+        .as_ref()
+        .is_some_and(|f| f.crop.is_some());
+    if options.auto_crop.unwrap_or(false) && !encode_options.remux.unwrap_or(false) && !has_crop {
+        log.section("crop detection");
+        let (input_path, duration, width, height, bit_depth) = (
+            options.input_path.clone(),
+            metadata.duration,
+            metadata.width,
+            metadata.height,
+            metadata.color.bit_depth(),
+        );
+        let detection = task::spawn_blocking(move || {
+            crop_detect::detect_crop(&input_path, duration, width, height, bit_depth)
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+        match detection {
+            Ok(CropDetection {
+                crop: Some(crop),
+                confidence,
+                samples,
+            }) => {
+                let agreement = format!("{:.0}% of {} samples", confidence * 100.0, samples);
+                if confidence >= MIN_AUTO_CROP_CONFIDENCE {
+                    log.line(&format!(
+                        "Cropping to {}x{} at {},{} ({})",
+                        crop.width, crop.height, crop.x, crop.y, agreement
+                    ));
+                    encode_options
+                        .filters
+                        .get_or_insert_with(Default::default)
+                        .crop = Some(crop);
+                } else {
+                    log.line(&format!("Crop too unstable, not applied ({})", agreement));
+                }
+            }
+            Ok(_) => log.line("No black bars found"),
+            Err(e) => {
+                let warning = format!("Crop detection failed, not cropped: {}", e);
+                eprintln!("[Crop] {}", warning);
+                log.line(&warning);
+                state
+                    .queue
+                    .lock()
+                    .unwrap()
+                    .add_warning(&options.id, warning);
+            }
+        }
+
+        // Sampling runs several ffmpeg processes that cannot be cancelled
+        if !wait_until_running(&state, &options.id).await {
+            let result = finish_cancellation(&options.id, &target, CancelMode::Abort, false).await;
+            eprintln!("[FFmpeg] {}", result.message);
+            log.line(&result.message);
+            let _ = app.emit("conversion_cancelled", result);
+            return Err(ConversionError::cancelled());
+        }
+    }
+
+    let mut built = build_command(&encode_options, &metadata);
+    if let (true, Err(e)) = (remux_by_rule, &built) {
+        // The rule only suggests a remux, fall back to the requested encode
+        let warning = format!("Remux not possible, encoding instead: {}", e);
+        eprintln!("[Preflight] {}", warning);
+        log.line(&warning);
+        state
+            .queue
+            .lock()
+            .unwrap()
+            .add_warning(&options.id, warning);
+        encode_options.remux = options.remux;
+        built = build_command(&encode_options, &metadata);
     }
 
-    // Map video stream first (required for video output)
-    args.push("-map".to_string());
-    args.push("0:v".to_string());
+    let mut plan = match built {
+        Ok(plan) => plan,
+        Err(e) => {
+            log.line(&format!("Invalid options: {}", e));
+            return Err(e.into());
+        }
+    };
 
-    // Audio Handling
-    let audio_codec = options.audio_codec.clone().unwrap_or("aac".to_string());
-    let audio_bitrate = options.audio_bitrate.clone().unwrap_or("128k".to_string());
+    if metadata.color.hdr == Some(HdrFormat::DolbyVision) && !encode_options.remux.unwrap_or(false)
+    {
+        // Re-encoding keeps the base layer only
+        let warning = "Dolby Vision metadata dropped, only the base layer is kept".to_string();
+        eprintln!("[FFmpeg] {}", warning);
+        log.line(&warning);
+        state
+            .queue
+            .lock()
+            .unwrap()
+            .add_warning(&options.id, warning);
+    }
 
-    match options.audio_strategy.as_deref() {
-        Some("copy_all") => {
-            args.push("-map".to_string());
-            args.push("0:a".to_string());
-            args.push("-c:a".to_string());
-            args.push("copy".to_string());
+    if !plan.dropped_tracks.is_empty() {
+        {
+            let mut queue = state.queue.lock().unwrap();
+            for track in &plan.dropped_tracks {
+                let warning = format!(
+                    "Subtitle track {} ({}{}) dropped: {}",
+                    track.index,
+                    track.codec_name,
+                    track
+                        .language
+                        .as_deref()
+                        .map(|l| format!(", {}", l))
+                        .unwrap_or_default(),
+                    track.reason
+                );
+                eprintln!("[FFmpeg] {}", warning);
+                log.line(&warning);
+                queue.add_warning(&options.id, warning);
+            }
         }
-        Some("convert_all") => {
-            args.push("-map".to_string());
-            args.push("0:a".to_string());
-            args.push("-c:a".to_string());
-            args.push(audio_codec.clone());
-            if audio_codec != "copy" {
-                args.push("-b:a".to_string());
-                args.push(audio_bitrate.clone());
+        emit_queue_updated(&app);
+    }
+
+    // Stream key=value progress blocks on stdout instead of the interactive
+    // stats line on stderr, which is not line-based when stderr is not a TTY
+    plan.global_flags.push("-progress".to_string());
+    plan.global_flags.push("pipe:1".to_string());
+    plan.global_flags.push("-nostats".to_string());
+
+    let passlog_dir = if plan.two_pass {
+        match PassLogDir::create(&options.id) {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                log.line(&e);
+                return Err(e.into());
             }
         }
-        _ => {
-            // Default or "first_track" or specific index
-            if let Some(index) = options.audio_track_index {
-                args.push("-map".to_string());
-                args.push(format!("0:{}", index));
+    } else {
+        None
+    };
+    // Loudness is measured first, the encode passes get the values once known
+    let loudness_target = options
+        .loudness
+        .filter(|_| !plan.converted_audio.is_empty());
+    let mut passes = Vec::new();
+    if let Some(target) = &loudness_target {
+        passes.push(plan.for_loudness_measurement(target));
+    }
+    if let Some(dir) = &passlog_dir {
+        let passlog = dir.path.join(PASSLOG_NAME).to_string_lossy().to_string();
+        passes.push(plan.for_pass(1, &passlog));
+        passes.push(plan.for_pass(2, &passlog));
+    } else {
+        passes.push(plan.clone());
+    }
+
+    let costs: Vec<f64> = passes
+        .iter()
+        .map(|p| {
+            if p.measures_loudness {
+                LOUDNESS_PASS_COST
             } else {
-                // FFmpeg default behavior is usually first track, but let's be explicit if needed
-                // For now, let's just set codec
+                1.0
             }
-            args.push("-c:a".to_string());
-            args.push(audio_codec);
-            if options.audio_codec.as_deref() != Some("copy") {
-                args.push("-b:a".to_string());
-                args.push(audio_bitrate);
+        })
+        .collect();
+
+    let mut loudness_measured = Vec::new();
+    let mut final_process = None;
+    for index in 0..passes.len() {
+        let pass_plan = passes[index].clone();
+        let span = PassSpan::new(index, &costs);
+        let is_last = index + 1 == passes.len();
+
+        // Cancelled before this pass, while no process was there to kill
+        if !wait_until_running(&state, &options.id).await {
+            let result = finish_cancellation(&options.id, &target, CancelMode::Abort, false).await;
+            eprintln!("[FFmpeg] {}", result.message);
+            log.line(&result.message);
+            let _ = app.emit("conversion_cancelled", result);
+            return Err(ConversionError::cancelled());
+        }
+
+        let outcome = match run_pass(&app, &options, &pass_plan, span, &mut log).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                output_file::discard(&target);
+                return Err(e.into());
             }
+        };
+
+        let cancel_mode = *outcome.process.cancel_mode.lock().unwrap();
+        if let Some(mode) = cancel_mode {
+            // Only the last pass writes an output that could be kept
+            let finished = is_last && outcome.status.success();
+            let result = finish_cancellation(&options.id, &target, mode, finished).await;
+            eprintln!("[FFmpeg] {}", result.message);
+            log.line(&result.message);
+            let _ = app.emit("conversion_cancelled", result);
+            return Err(ConversionError::cancelled());
         }
-    }
 
-    // Subtitle Handling
-    match options.subtitle_strategy.as_deref() {
-        Some("copy_all") => {
-            args.push("-map".to_string());
-            args.push("0:s".to_string());
-            args.push("-c:s".to_string());
+        if !outcome.status.success() {
+            output_file::discard(&target);
+            let error = classify(&outcome.stderr_tail, outcome.status.code());
+            eprintln!("[FFmpeg] Conversion {} failed: {}", options.id, error);
+            log.line(&format!(
+                "Failed: {} (exit code {:?})",
+                error,
+                outcome.status.code()
+            ));
+            return Err(error);
+        }
 
-            // Check if output is MP4
-            if options.output_path.to_lowercase().ends_with(".mp4") {
-                // MP4 doesn't support many subtitle formats (like PGS, ASS) natively in the same way MKV does.
-                // 'mov_text' is the standard text subtitle format for MP4.
-                // However, this will fail for image-based subtitles (PGS/VOBSUB).
-                // Ideally we should detect input codec, but for now, let's try mov_text as it's safer than copy for text subs.
-                // If it's PGS, it might still fail, but 'copy' definitely fails.
-                args.push("mov_text".to_string());
-            } else {
-                args.push("copy".to_string());
+        if let (true, Some(target)) = (pass_plan.measures_loudness, &loudness_target) {
+            log.section("loudness");
+            match loudness::parse_measurements(
+                outcome.stderr_tail.lines(),
+                &pass_plan.converted_audio,
+            ) {
+                Ok(measured) => {
+                    for m in &measured {
+                        log.line(&format!(
+                            "Track {} (stream {}): {:.1} LUFS, {:.1} dBTP, {:.1} LU",
+                            m.track, m.stream_index, m.integrated, m.true_peak, m.range
+                        ));
+                    }
+                    for later in &mut passes[index + 1..] {
+                        later.apply_loudness(target, &measured);
+                    }
+                    // Silent tracks measure -inf, which JSON cannot hold
+                    loudness_measured = measured.into_iter().filter(|m| m.is_usable()).collect();
+                }
+                Err(e) => {
+                    let warning = format!("Loudness not normalized: {}", e);
+                    eprintln!("[FFmpeg] {}", warning);
+                    log.line(&warning);
+                    state
+                        .queue
+                        .lock()
+                        .unwrap()
+                        .add_warning(&options.id, warning);
+                }
             }
         }
-        Some("ignore") => {
-            args.push("-sn".to_string());
+
+        final_process = Some(outcome.process);
+    }
+    let process = final_process.ok_or_else(|| "No ffmpeg pass was run".to_string())?;
+
+    let output_metadata = match finalize_output(&target).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            output_file::discard(&target);
+            let error = ConversionError::invalid_output(&e);
+            eprintln!("[FFmpeg] Conversion {} failed: {}", options.id, error);
+            log.line(&format!("Failed: {}", error));
+            return Err(error);
         }
-        _ => {
-            if let Some(index) = options.subtitle_track_index {
-                args.push("-map".to_string());
-                args.push(format!("0:{}", index));
-                args.push("-c:s".to_string());
-                args.push("mov_text".to_string());
+    };
+    log.line(&format!("Moved output to {}", target.destination.display()));
+
+    // A zero exit code does not guarantee the output is what was asked for
+    let mismatches = output_file::verify_output(&plan, &options, &output_metadata);
+    if !mismatches.is_empty() {
+        log.section("verification");
+        {
+            let mut queue = state.queue.lock().unwrap();
+            for mismatch in mismatches {
+                eprintln!("[FFmpeg] {}: {}", options.id, mismatch);
+                log.line(&mismatch);
+                queue.add_warning(&options.id, mismatch);
             }
         }
+        emit_queue_updated(&app);
     }
 
-    // Create a temporary progress file
-    let progress_file = std::env::temp_dir().join(format!("ffmpeg_progress_{}.txt", options.id));
-    let progress_file_str = progress_file.to_string_lossy().to_string();
+    let frames = process
+        .last_progress
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|p| p.frame)
+        .unwrap_or(0);
+    let encode_seconds = process.clock.lock().unwrap().elapsed();
+    let report = ConversionReport {
+        id: options.id.clone(),
+        output_path: target.destination.to_string_lossy().to_string(),
+        input_size: metadata.size,
+        output_size: output_metadata.size,
+        compression_ratio: if metadata.size > 0 {
+            output_metadata.size as f64 / metadata.size as f64
+        } else {
+            0.0
+        },
+        average_fps: if encode_seconds > 0.0 {
+            frames as f64 / encode_seconds
+        } else {
+            0.0
+        },
+        wall_time_seconds: started.elapsed().as_secs_f64(),
+        bitrate_kbps: (output_metadata.duration > 0.0)
+            .then(|| output_metadata.size as f64 * 8.0 / output_metadata.duration / 1000.0),
+        command: passes
+            .iter()
+            .map(|p| p.to_command_line())
+            .collect::<Vec<_>>()
+            .join(" && "),
+        loudness: loudness_measured,
+    };
+
+    eprintln!(
+        "[FFmpeg] Conversion completed successfully for {}",
+        options.id
+    );
+    log.line("Completed successfully");
+    log.line(&format!(
+        "Size: {} -> {} bytes ({:.1}%), {:.1} fps average, {}",
+        report.input_size,
+        report.output_size,
+        report.compression_ratio * 100.0,
+        report.average_fps,
+        format_hms(report.wall_time_seconds)
+    ));
+
+    let _ = app.emit("conversion_completed", report.clone());
+
+    Ok(Some(report))
+}
 
-    // Use -progress to force FFmpeg to write progress to a file
-    // This is the most reliable way to get progress when stderr is not a TTY
-    args.push("-progress".to_string());
-    args.push(progress_file_str.clone());
+/// How one ffmpeg run ended.
+struct PassOutcome {
+    status: std::process::ExitStatus,
+    stderr_tail: StderrRing,
+    process: ActiveProcess,
+}
 
-    args.push("-y".to_string());
-    args.push(options.output_path.clone());
+/// " (pass 1/2)" for multi-pass encodes, nothing otherwise.
+fn pass_label(span: PassSpan) -> String {
+    if span.count > 1 {
+        format!(" (pass {}/{})", span.index + 1, span.count)
+    } else {
+        String::new()
+    }
+}
+
+/// Spawns ffmpeg for one pass and waits for it to exit, reporting progress
+/// as the `span` share of the job. The process is in `processes` meanwhile,
+/// so it can be paused and cancelled.
+async fn run_pass(
+    app: &AppHandle,
+    options: &ConversionOptions,
+    plan: &CommandPlan,
+    span: PassSpan,
+    log: &mut JobLog,
+) -> Result<PassOutcome, String> {
+    let state = app.state::<ConversionManager>();
+    let label = pass_label(span);
+    let args = plan.to_args();
 
     // Log the command for debugging
-    eprintln!("[FFmpeg Command]: ffmpeg {}", args.join(" "));
+    eprintln!("[FFmpeg Command]{}: {}", label, plan.to_command_line());
+    log.section(&format!("command{}", label));
+    log.line(&plan.to_command_line());
 
     let mut cmd = Command::new("ffmpeg");
+    // stdin stays open so "q" can be sent to stop gracefully
     cmd.args(&args)
+        .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
@@ -248,26 +1041,44 @@ pub async fn convert_video(
             e
         );
         eprintln!("{}", error_msg);
+        log.line(&error_msg);
         error_msg
     })?;
 
-    // Capture stderr before moving child
-    let stderr = child.stderr.take().ok_or("Failed to capture stderr")?;
-
+    // Capture stdin/stdout/stderr before moving child
+    let stdin = child.stdin.take();
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "Failed to capture stdout".to_string())?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| "Failed to capture stderr".to_string())?;
+
+    let pid = child.id();
     let child_shared = Arc::new(TokioMutex::new(child));
+    let process = ActiveProcess {
+        child: child_shared.clone(),
+        pid,
+        clock: Arc::new(Mutex::new(ActiveClock::start())),
+        last_progress: Arc::new(Mutex::new(None)),
+        stdin: Arc::new(TokioMutex::new(stdin)),
+        cancel_mode: Arc::new(Mutex::new(None)),
+    };
 
     state
         .processes
         .lock()
         .unwrap()
-        .insert(options.id.clone(), child_shared.clone());
+        .insert(options.id.clone(), process.clone());
 
     // Enable "Stop" button in Tray
-    crate::set_stop_enabled(window.app_handle(), true);
+    crate::set_stop_enabled(app, true);
+    refresh_tray_pause_items(app);
 
-    // Spawn a task to read the progress file
-    let progress_file_clone = progress_file.clone();
-    let window_clone = window.clone();
+    // Spawn a task to read progress blocks from stdout
+    let app_clone = app.clone();
     let options_id = options.id.clone();
     let options_filename = std::path::Path::new(&options.input_path)
         .file_name()
@@ -275,281 +1086,475 @@ pub async fn convert_video(
         .unwrap_or("Unknown")
         .to_string();
     let options_duration = options.duration_seconds;
-    let options_total_frames = options.total_frames;
+    // The frame count changes with an fps filter
+    let output_frames = plan.output_frames;
 
-    // Create a channel to signal when conversion is done
-    let (tx, mut rx) = tokio::sync::oneshot::channel::<()>();
-    let tx_clone = tx;
+    let clock = process.clock.clone();
+    let last_progress = process.last_progress.clone();
 
     let progress_task = task::spawn(async move {
-        use std::collections::VecDeque;
-        use tokio::time::{sleep, Duration, Instant};
-
-        // History to calculate average speed over last 10 seconds
-        // Store (timestamp, frame_count) pairs
-        let mut frame_history: VecDeque<(Instant, u64)> = VecDeque::new();
-        const HISTORY_DURATION_SECS: u64 = 10;
-
-        // Track when conversion started
-        let start_time = Instant::now();
-
-        // Wait a bit for the file to be created
-        sleep(Duration::from_millis(500)).await;
-
-        loop {
-            // Check if we should stop
-            if rx.try_recv().is_ok() {
-                break;
-            }
-
-            // Read the entire progress file and parse it
-            if let Ok(content) = tokio::fs::read_to_string(&progress_file_clone).await {
-                let mut frame = 0u64;
-                let mut fps = 0.0;
-                let mut bitrate = String::new();
-                let mut speed = String::new();
-                let mut time_ms = 0.0;
-
-                for line in content.lines() {
-                    if line.starts_with("frame=") {
-                        if let Some(val) = line.strip_prefix("frame=") {
-                            frame = val.trim().parse().unwrap_or(0);
-                        }
-                    } else if line.starts_with("fps=") {
-                        if let Some(val) = line.strip_prefix("fps=") {
-                            fps = val.trim().parse().unwrap_or(0.0);
-                        }
-                    } else if line.starts_with("bitrate=") {
-                        if let Some(val) = line.strip_prefix("bitrate=") {
-                            bitrate = val.trim().to_string();
-                        }
-                    } else if line.starts_with("speed=") {
-                        if let Some(val) = line.strip_prefix("speed=") {
-                            speed = val.trim().to_string();
-                        }
-                    } else if line.starts_with("out_time_ms=") {
-                        if let Some(val) = line.strip_prefix("out_time_ms=") {
-                            time_ms = val.trim().parse().unwrap_or(0.0);
-                        }
-                    }
-                }
+        let mut parser = ProgressParser::new();
+        let mut estimator = ProgressEstimator::new(options_duration, output_frames);
 
-                // Only emit if we have valid frame data
-                if frame > 0 {
-                    let current_seconds = if time_ms > 0.0 {
-                        time_ms / 1_000_000.0 // Convert microseconds to seconds
-                    } else {
-                        0.0
-                    };
-
-                    // Calculate progress based on frames if we have total_frames
-                    let progress = if let Some(total_frames) = options_total_frames {
-                        if total_frames > 0 {
-                            ((frame as f64 / total_frames as f64) * 100.0)
-                                .min(100.0)
-                                .max(0.0)
-                        } else {
-                            0.0
-                        }
-                    } else if options_duration > 0.0 && current_seconds > 0.0 {
-                        // Fallback to time-based if frames not available
-                        (current_seconds / options_duration * 100.0)
-                            .min(100.0)
-                            .max(0.0)
-                    } else {
-                        0.0
-                    };
-
-                    // Update frame history for average speed calculation
-                    let now = Instant::now();
-                    frame_history.push_back((now, frame));
-
-                    // Remove entries older than HISTORY_DURATION_SECS
-                    while let Some(&(timestamp, _)) = frame_history.front() {
-                        if now.duration_since(timestamp).as_secs() > HISTORY_DURATION_SECS {
-                            frame_history.pop_front();
-                        } else {
-                            break;
-                        }
-                    }
+        // Last whole percent written to the queue file
+        let mut last_saved_percent = 0u64;
 
-                    // Calculate average frames per second over the last 10 seconds
-                    let avg_fps = if frame_history.len() >= 2 {
-                        let oldest = frame_history.front().unwrap();
-                        let newest = frame_history.back().unwrap();
-                        let time_diff = newest.0.duration_since(oldest.0).as_secs_f64();
-                        let frame_diff = newest.1.saturating_sub(oldest.1) as f64;
-
-                        if time_diff > 0.0 {
-                            frame_diff / time_diff
-                        } else {
-                            fps // Fallback to current fps
-                        }
-                    } else {
-                        fps // Use current fps if not enough history
-                    };
-
-                    // Calculate time remaining based on average speed
-                    let time_remaining = if let Some(total_frames) = options_total_frames {
-                        if total_frames > frame && avg_fps > 0.0 {
-                            let remaining_frames = (total_frames - frame) as f64;
-                            let remaining_seconds = remaining_frames / avg_fps;
-                            Some(remaining_seconds)
-                        } else {
-                            None
-                        }
-                    } else if options_duration > 0.0 && current_seconds > 0.0 && avg_fps > 0.0 {
-                        // Fallback: estimate based on duration and current progress
-                        let remaining_seconds = (options_duration - current_seconds).max(0.0);
-                        Some(remaining_seconds)
-                    } else {
-                        None
-                    };
-
-                    // Format time remaining
-                    let time_remaining_str = time_remaining.map(|secs| {
-                        let hours = (secs as u64) / 3600;
-                        let minutes = ((secs as u64) % 3600) / 60;
-                        let secs_remaining = (secs as u64) % 60;
-                        format!("{:02}:{:02}:{:02}", hours, minutes, secs_remaining)
-                    });
-
-                    // Format current time (position in output video)
-                    let time_str = if current_seconds > 0.0 {
-                        let hours = (current_seconds as u64) / 3600;
-                        let minutes = ((current_seconds as u64) % 3600) / 60;
-                        let secs = (current_seconds as u64) % 60;
-                        format!("{:02}:{:02}:{:02}", hours, minutes, secs)
-                    } else {
-                        String::new()
-                    };
-
-                    // Calculate elapsed time since conversion started
-                    let elapsed_seconds = start_time.elapsed().as_secs();
-                    let elapsed_hours = elapsed_seconds / 3600;
-                    let elapsed_minutes = (elapsed_seconds % 3600) / 60;
-                    let elapsed_secs = elapsed_seconds % 60;
-                    let elapsed_time_str = format!(
-                        "{:02}:{:02}:{:02}",
-                        elapsed_hours, elapsed_minutes, elapsed_secs
-                    );
+        let mut lines = BufReader::new(stdout).lines();
 
-                    // Only emit if progress is reasonable (not jumping to 100% immediately)
-                    if progress < 100.0 || frame >= options_total_frames.unwrap_or(u64::MAX) {
-                        let progress_data = ConversionProgress {
-                            id: options_id.clone(),
-                            frame,
-                            fps,
-                            time: time_str,
-                            elapsed_time: elapsed_time_str,
-                            bitrate,
-                            speed,
-                            progress,
-                            time_remaining: time_remaining_str.clone(),
-                        };
-
-                        let _ = window_clone
-                            .app_handle()
-                            .emit("conversion_progress", progress_data);
-
-                        // Update Tray Status
-                        // Update Tray Status
-                        let display_name = if options_filename.len() > 20 {
-                            format!(
-                                "{}...",
-                                options_filename.chars().take(20).collect::<String>()
-                            )
-                        } else {
-                            options_filename.clone()
-                        };
-
-                        let tray_text = format!(
-                            "Converting: {} ({:.0}%) - {}",
-                            display_name,
-                            progress,
-                            time_remaining_str.as_deref().unwrap_or("--:--")
-                        );
-                        crate::update_tray_status(window_clone.app_handle(), &tray_text);
-                    }
-                }
+        while let Ok(Some(line)) = lines.next_line().await {
+            let block = match parser.push_line(&line) {
+                Some(block) => block,
+                None => continue,
+            };
+
+            // Nothing encoded yet
+            if block.frame.unwrap_or(0) == 0 && block.out_time_us.is_none() && !block.finished {
+                continue;
+            }
+
+            let elapsed = clock.lock().unwrap().elapsed();
+            let estimate = span.apply(estimator.update(&block, elapsed), elapsed);
+            let time_remaining_str = estimate.time_remaining.map(format_hms);
+
+            let progress_data = ConversionProgress {
+                id: options_id.clone(),
+                frame: block.frame.unwrap_or(0),
+                fps: block.fps.unwrap_or(0.0),
+                time: estimate.current_seconds.map(format_hms).unwrap_or_default(),
+                elapsed_time: format_hms(elapsed),
+                bitrate: block.bitrate.clone().unwrap_or_default(),
+                speed: block.speed.clone().unwrap_or_default(),
+                progress: estimate.progress,
+                time_remaining: time_remaining_str.clone(),
+                paused: false,
+            };
+
+            *last_progress.lock().unwrap() = Some(progress_data.clone());
+            let _ = app_clone.emit("conversion_progress", progress_data);
+
+            app_clone
+                .state::<ConversionManager>()
+                .queue
+                .lock()
+                .unwrap()
+                .set_progress(&options_id, estimate.progress);
+
+            if estimate.progress as u64 > last_saved_percent {
+                last_saved_percent = estimate.progress as u64;
+                persist_queue(&app_clone);
             }
 
-            sleep(Duration::from_millis(200)).await; // Check every 200ms
+            // Update Tray Status
+            let display_name = if options_filename.len() > 20 {
+                format!(
+                    "{}...",
+                    options_filename.chars().take(20).collect::<String>()
+                )
+            } else {
+                options_filename.clone()
+            };
+
+            let tray_text = format!(
+                "Converting: {} ({:.0}%) - {}",
+                display_name,
+                estimate.progress,
+                time_remaining_str.as_deref().unwrap_or("--:--")
+            );
+            crate::update_tray_status(&app_clone, &tray_text);
         }
     });
 
     // Read stderr for errors and warnings
     let reader = BufReader::new(stderr);
     let mut lines = reader.lines();
+    // Room for the loudnorm JSON, read once ffmpeg has exited
+    let mut stderr_tail = StderrRing::with_extra(if plan.measures_loudness {
+        plan.converted_audio.len() * loudness::LINES_PER_TRACK
+    } else {
+        0
+    });
+    log.section(&format!("ffmpeg stderr{}", label));
 
     while let Ok(Some(line)) = lines.next_line().await {
         // Log stderr lines (warnings, errors)
         eprintln!("[FFmpeg Stderr]: {}", line);
+        log.line(&line);
+        stderr_tail.push(line);
     }
 
-    // Signal progress task to stop
-    let _ = tx_clone.send(());
+    // stdout closes when ffmpeg exits, which ends the progress task
+    let _ = progress_task.await;
 
-    // Wait a bit for the task to finish, then abort if needed
-    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
-    progress_task.abort();
+    let status = child_shared.lock().await.wait().await;
 
-    // Clean up progress file
-    let _ = std::fs::remove_file(&progress_file);
+    // Remove PID from map
+    state.processes.lock().unwrap().remove(&options.id);
 
-    let status = child_shared.lock().await.wait().await.map_err(|e| {
+    log.section(&format!("result{}", label));
+    let status = status.map_err(|e| {
         let error_msg = format!("Failed to wait for ffmpeg: {}", e);
         eprintln!("{}", error_msg);
+        log.line(&error_msg);
         error_msg
     })?;
 
-    // Remove PID from map
-    state.processes.lock().unwrap().remove(&options.id);
+    Ok(PassOutcome {
+        status,
+        stderr_tail,
+        process,
+    })
+}
 
-    if !status.success() {
-        let exit_code = status.code().unwrap_or(-1);
-        let error_msg = format!(
-            "FFmpeg exited with error code: {}. Check the console output above for details.",
-            exit_code
-        );
-        eprintln!("{}", error_msg);
-        crate::set_stop_enabled(window.app_handle(), false);
-        return Err(error_msg);
+/// Probes the temporary file and moves it over the destination.
+async fn finalize_output(target: &OutputTarget) -> Result<VideoMetadata, String> {
+    let temp = target.temp.to_string_lossy().to_string();
+    let metadata = task::spawn_blocking(move || extract_metadata(&temp))
+        .await
+        .map_err(|e| format!("Failed to probe output: {}", e))??;
+    output_file::check_output(&metadata)?;
+    output_file::commit(target)?;
+    Ok(metadata)
+}
+
+/// Waits while the job is paused without an ffmpeg process, e.g. between
+/// two passes, and tells whether it is still meant to run. False once it
+/// was cancelled.
+async fn wait_until_running(state: &ConversionManager, id: &str) -> bool {
+    loop {
+        let status = state.queue.lock().unwrap().get(id).map(|j| j.status);
+        match status {
+            Some(JobStatus::Running) => return true,
+            Some(JobStatus::Suspended) => tokio::time::sleep(SUSPENDED_POLL_INTERVAL).await,
+            _ => return false,
+        }
     }
+}
 
-    eprintln!(
-        "[FFmpeg] Conversion completed successfully for {}",
-        options.id
-    );
+/// Discards the partial output unless ffmpeg finalized it after a graceful
+/// stop. The destination is never touched by a cancelled job.
+async fn finish_cancellation(
+    id: &str,
+    target: &OutputTarget,
+    mode: CancelMode,
+    finished: bool,
+) -> CancellationResult {
+    let output_kept = if mode == CancelMode::StopAndKeep && finished {
+        match finalize_output(target).await {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("[FFmpeg] {}", e);
+                false
+            }
+        }
+    } else {
+        false
+    };
+
+    if !output_kept {
+        output_file::discard(target);
+    }
+
+    let output_path = target.destination.to_string_lossy().to_string();
+    let message = match (mode, output_kept) {
+        (CancelMode::StopAndKeep, true) => format!("Stopped early, kept output {}", output_path),
+        (CancelMode::StopAndKeep, false) => {
+            "Stopped, but the output could not be finalized so it was discarded".to_string()
+        }
+        (CancelMode::Abort, _) => "Cancelled, partial output discarded".to_string(),
+    };
+
+    CancellationResult {
+        id: id.to_string(),
+        mode,
+        output_path,
+        output_kept,
+        message,
+    }
+}
+
+/// Sends "q" on stdin, after which ffmpeg stops reading input and writes the
+/// container trailer. Killed anyway if it does not exit in time.
+async fn request_stop(app: &AppHandle, id: &str, process: &ActiveProcess) -> Result<(), String> {
+    // A suspended process would never read its stdin
+    let _ = resume_process(process.pid);
+    process.clock.lock().unwrap().resume();
 
-    // Reset Tray Status
-    crate::update_tray_status(window.app_handle(), "No active conversions");
-    crate::set_stop_enabled(window.app_handle(), false);
+    {
+        let mut stdin = process.stdin.lock().await;
+        let pipe = stdin
+            .as_mut()
+            .ok_or_else(|| "ffmpeg stdin is not available".to_string())?;
+        pipe.write_all(b"q")
+            .await
+            .map_err(|e| format!("Failed to send stop request: {}", e))?;
+        pipe.flush()
+            .await
+            .map_err(|e| format!("Failed to send stop request: {}", e))?;
+    }
+
+    let app_handle = app.clone();
+    let id = id.to_string();
+    let child = process.child.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(GRACEFUL_STOP_TIMEOUT).await;
+        let still_running = app_handle
+            .state::<ConversionManager>()
+            .processes
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|p| Arc::ptr_eq(&p.child, &child))
+            .unwrap_or(false);
+        if still_running {
+            eprintln!("[FFmpeg] {} did not stop in time, killing it", id);
+            let _ = child.lock().await.start_kill();
+        }
+    });
 
     Ok(())
 }
 
 pub async fn cancel_conversion(
+    app: &AppHandle,
     id: &str,
+    mode: CancelMode,
     state: State<'_, ConversionManager>,
 ) -> Result<(), String> {
-    let child_arc = {
-        let mut processes = state.processes.lock().unwrap();
-        processes.remove(id)
+    let was_active = {
+        let mut queue = state.queue.lock().unwrap();
+        match queue.get(id).map(|j| j.status) {
+            Some(status) if !status.is_finished() => {
+                queue.set_status(id, JobStatus::Cancelled, None);
+                true
+            }
+            _ => false,
+        }
     };
 
-    if let Some(child_arc) = child_arc {
-        let mut child = child_arc.lock().await;
+    // The process stays in the map until convert_video sees it exit
+    let process = state.processes.lock().unwrap().get(id).cloned();
+
+    if let Some(process) = process {
+        *process.cancel_mode.lock().unwrap() = Some(mode);
+
+        if mode == CancelMode::StopAndKeep {
+            match request_stop(app, id, &process).await {
+                Ok(()) => return Ok(()),
+                Err(e) => eprintln!("[FFmpeg] {}, killing instead", e),
+            }
+        }
+
+        let mut child = process.child.lock().await;
         child
             .start_kill()
             .map_err(|e| format!("Failed to kill process: {}", e))?;
         Ok(())
+    } else if was_active {
+        // Queued, or running without ffmpeg between two passes. convert_video
+        // sees the status before it starts the next process.
+        emit_queue_updated(app);
+        Ok(())
     } else {
         Err("Process not found".to_string())
     }
 }
 
-pub async fn kill_all(state: State<'_, ConversionManager>) {
+pub fn remove_job(app: &AppHandle, id: &str) -> Result<(), String> {
+    {
+        let state = app.state::<ConversionManager>();
+        state.queue.lock().unwrap().remove(id)?;
+    }
+    emit_queue_updated(app);
+    Ok(())
+}
+
+pub fn move_job(app: &AppHandle, id: &str, new_index: usize) -> Result<(), String> {
+    {
+        let state = app.state::<ConversionManager>();
+        state.queue.lock().unwrap().move_job(id, new_index)?;
+    }
+    emit_queue_updated(app);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn signal_process(pid: Option<u32>, signal: libc::c_int) -> Result<(), String> {
+    let pid = pid.ok_or_else(|| "Process has already exited".to_string())?;
+    // SAFETY: kill only sends a signal, the pid belongs to our own child
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to signal process {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+#[cfg(unix)]
+fn suspend_process(pid: Option<u32>) -> Result<(), String> {
+    signal_process(pid, libc::SIGSTOP)
+}
+
+#[cfg(unix)]
+fn resume_process(pid: Option<u32>) -> Result<(), String> {
+    signal_process(pid, libc::SIGCONT)
+}
+
+#[cfg(not(unix))]
+fn suspend_process(_pid: Option<u32>) -> Result<(), String> {
+    Err("Pausing a running conversion is not supported on this platform".to_string())
+}
+
+#[cfg(not(unix))]
+fn resume_process(_pid: Option<u32>) -> Result<(), String> {
+    Err("Resuming a running conversion is not supported on this platform".to_string())
+}
+
+/// Re-sends the last progress of a running job with its paused flag, so the
+/// frontend can show the state without waiting for ffmpeg output.
+fn emit_paused_state(app: &AppHandle, process: &ActiveProcess, paused: bool) {
+    let mut last_progress = process.last_progress.lock().unwrap();
+    if let Some(progress) = last_progress.as_mut() {
+        progress.paused = paused;
+        progress.elapsed_time = format_hms(process.clock.lock().unwrap().elapsed());
+        let _ = app.emit("conversion_progress", progress.clone());
+
+        if paused {
+            crate::update_tray_status(app, &format!("Paused ({:.0}%)", progress.progress));
+        }
+    }
+}
+
+/// Enables the tray pause and resume entries depending on what is running.
+fn refresh_tray_pause_items(app: &AppHandle) {
+    let state = app.state::<ConversionManager>();
+    let (can_pause, can_resume) = {
+        let queue = state.queue.lock().unwrap();
+        (
+            queue.first_with_status(JobStatus::Running).is_some(),
+            queue.first_with_status(JobStatus::Suspended).is_some(),
+        )
+    };
+    crate::set_pause_enabled(app, can_pause, can_resume);
+}
+
+/// Pauses a queued job, or suspends the ffmpeg process of a running one.
+/// Between two passes there is no process, the next pass waits instead.
+pub fn pause_job(app: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app.state::<ConversionManager>();
+    let suspended = {
+        let mut queue = state.queue.lock().unwrap();
+        let process = state.processes.lock().unwrap().get(id).cloned();
+        match (queue.get(id).map(|j| j.status), process) {
+            (Some(JobStatus::Running), Some(process)) => {
+                suspend_process(process.pid)?;
+                process.clock.lock().unwrap().pause();
+                queue.pause(id)?;
+                Some(process)
+            }
+            _ => {
+                queue.pause(id)?;
+                None
+            }
+        }
+    };
+
+    if let Some(process) = suspended {
+        eprintln!("[FFmpeg] Suspended conversion {}", id);
+        emit_paused_state(app, &process, true);
+    }
+    refresh_tray_pause_items(app);
+    emit_queue_updated(app);
+    Ok(())
+}
+
+/// Resumes a paused queued job, or continues a suspended ffmpeg process.
+pub fn resume_job(app: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app.state::<ConversionManager>();
+    let resumed = {
+        let mut queue = state.queue.lock().unwrap();
+        let process = state.processes.lock().unwrap().get(id).cloned();
+        match (queue.get(id).map(|j| j.status), process) {
+            (Some(JobStatus::Suspended), Some(process)) => {
+                resume_process(process.pid)?;
+                process.clock.lock().unwrap().resume();
+                queue.resume(id)?;
+                Some(process)
+            }
+            _ => {
+                queue.resume(id)?;
+                None
+            }
+        }
+    };
+
+    if let Some(process) = resumed {
+        eprintln!("[FFmpeg] Resumed conversion {}", id);
+        emit_paused_state(app, &process, false);
+    }
+    refresh_tray_pause_items(app);
+    emit_queue_updated(app);
+    process_queue(app);
+    Ok(())
+}
+
+fn first_job_with_status(app: &AppHandle, status: JobStatus) -> Option<String> {
+    let state = app.state::<ConversionManager>();
+    let queue = state.queue.lock().unwrap();
+    queue.first_with_status(status).map(|j| j.id.clone())
+}
+
+pub fn pause_current(app: &AppHandle) {
+    if let Some(id) = first_job_with_status(app, JobStatus::Running) {
+        if let Err(e) = pause_job(app, &id) {
+            eprintln!("[FFmpeg] {}", e);
+        }
+    }
+}
+
+pub fn resume_current(app: &AppHandle) {
+    if let Some(id) = first_job_with_status(app, JobStatus::Suspended) {
+        if let Err(e) = resume_job(app, &id) {
+            eprintln!("[FFmpeg] {}", e);
+        }
+    }
+}
+
+pub fn set_max_parallel(app: &AppHandle, max_parallel: usize) {
+    {
+        let state = app.state::<ConversionManager>();
+        state.queue.lock().unwrap().set_max_parallel(max_parallel);
+    }
+    emit_queue_updated(app);
+    process_queue(app);
+}
+
+pub fn restart_job(app: &AppHandle, id: &str) -> Result<(), String> {
+    {
+        let state = app.state::<ConversionManager>();
+        state.queue.lock().unwrap().restart(id)?;
+    }
+    emit_queue_updated(app);
+    process_queue(app);
+    Ok(())
+}
+
+pub fn clear_finished_jobs(app: &AppHandle) {
+    {
+        let state = app.state::<ConversionManager>();
+        state.queue.lock().unwrap().clear_finished();
+    }
+    emit_queue_updated(app);
+}
+
+pub async fn kill_all(app: &AppHandle, state: State<'_, ConversionManager>) {
+    // Save running jobs as interrupted before killing them, so they are
+    // offered for restart on the next launch instead of being reported failed
+    state.queue.lock().unwrap().interrupt_running();
+    persist_queue(app);
+
     let processes = {
         let mut map = state.processes.lock().unwrap();
         let items: Vec<_> = map.drain().collect();
@@ -561,21 +1566,21 @@ pub async fn kill_all(state: State<'_, ConversionManager>) {
         processes.len()
     );
 
-    for (id, child_arc) in processes {
+    for (id, process) in processes {
         eprintln!("[FFmpeg] Killing process for conversion {}", id);
-        let mut child = child_arc.lock().await;
+        let mut child = process.child.lock().await;
         if let Err(e) = child.start_kill() {
             eprintln!("[FFmpeg] Failed to kill process {}: {}", id, e);
         }
     }
 }
 
-pub async fn cancel_current(state: State<'_, ConversionManager>) {
+pub async fn cancel_current(app: &AppHandle, state: State<'_, ConversionManager>) {
     let id_to_cancel = {
         let processes = state.processes.lock().unwrap();
         processes.keys().next().cloned()
     };
     if let Some(id) = id_to_cancel {
-        let _ = cancel_conversion(&id, state).await;
+        let _ = cancel_conversion(app, &id, CancelMode::Abort, state).await;
     }
 }
@@ -0,0 +1,218 @@
+use crate::modules::ffmpeg_error::ConversionError;
+use crate::modules::ffmpeg_runner::{ConversionOptions, ConversionReport};
+use crate::modules::job_queue::{ConversionJob, JobStatus};
+use crate::modules::preflight::PreflightDecision;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// One finished job. The history file holds one of these per line, oldest
+/// first, and is only ever appended to.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub id: String, // "{job_id}-{finished_at}"
+    pub job_id: String,
+    pub input_path: String,
+    pub output_path: String,
+    pub options: ConversionOptions,
+    pub status: JobStatus,
+    pub report: Option<ConversionReport>,
+    pub error: Option<ConversionError>,
+    pub warnings: Vec<String>,
+    #[serde(default)]
+    pub preflight: Option<PreflightDecision>,
+    pub started_at: u64,  // Unix seconds
+    pub finished_at: u64, // Unix seconds
+}
+
+impl HistoryEntry {
+    pub fn from_job(job: &ConversionJob, started_at: u64, finished_at: u64) -> Self {
+        Self {
+            id: format!("{}-{}", job.id, finished_at),
+            job_id: job.id.clone(),
+            input_path: job.options.input_path.clone(),
+            output_path: job.output_path().to_string(),
+            options: job.options.clone(),
+            status: job.status,
+            report: job.report.clone(),
+            error: job.error.clone(),
+            warnings: job.warnings.clone(),
+            preflight: job.preflight.clone(),
+            started_at,
+            finished_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HistoryFilter {
+    pub statuses: Vec<JobStatus>, // Empty matches every status
+    pub since: Option<u64>,       // Unix seconds, on finished_at
+    pub until: Option<u64>,
+    pub search: Option<String>, // Case-insensitive match on input or output path
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        if !self.statuses.is_empty() && !self.statuses.contains(&entry.status) {
+            return false;
+        }
+        if self.since.map(|t| entry.finished_at < t).unwrap_or(false) {
+            return false;
+        }
+        if self.until.map(|t| entry.finished_at > t).unwrap_or(false) {
+            return false;
+        }
+        if let Some(search) = self.search.as_deref().filter(|s| !s.is_empty()) {
+            let search = search.to_lowercase();
+            if !entry.input_path.to_lowercase().contains(&search)
+                && !entry.output_path.to_lowercase().contains(&search)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub fn append(path: &Path, entry: &HistoryEntry) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create history directory: {}", e))?;
+    }
+
+    let line = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize history entry: {}", e))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open history file: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write history file: {}", e))
+}
+
+/// Entries matching `filter`, newest first. Lines that fail to parse, e.g.
+/// a write cut short by a crash, are skipped.
+pub fn query(path: &Path, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read history file: {}", e))?;
+
+    let mut entries: Vec<HistoryEntry> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<HistoryEntry>(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("[History] Skipping unreadable entry: {}", e);
+                None
+            }
+        })
+        .filter(|entry| filter.matches(entry))
+        .collect();
+
+    entries.reverse();
+    if let Some(limit) = filter.limit {
+        entries.truncate(limit);
+    }
+    Ok(entries)
+}
+
+pub fn find(path: &Path, id: &str) -> Result<HistoryEntry, String> {
+    query(path, &HistoryFilter::default())?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| format!("History entry {} not found", id))
+}
+
+/// Formats Unix seconds as an ISO 8601 UTC timestamp.
+fn format_timestamp(secs: u64) -> String {
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn status_name(status: JobStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+/// Writes the entries matching `filter` to `destination` as CSV.
+pub fn export_csv(
+    path: &Path,
+    filter: &HistoryFilter,
+    destination: &Path,
+) -> Result<usize, String> {
+    let entries = query(path, filter)?;
+
+    let mut csv = String::from(
+        "started_at,finished_at,status,input_path,output_path,video_codec,input_size,output_size,compression_ratio,wall_time_seconds,error\n",
+    );
+    for entry in &entries {
+        let report = entry.report.as_ref();
+        let row = [
+            format_timestamp(entry.started_at),
+            format_timestamp(entry.finished_at),
+            status_name(entry.status),
+            entry.input_path.clone(),
+            entry.output_path.clone(),
+            entry.options.video_codec.ffmpeg_name().to_string(),
+            report.map(|r| r.input_size.to_string()).unwrap_or_default(),
+            report
+                .map(|r| r.output_size.to_string())
+                .unwrap_or_default(),
+            report
+                .map(|r| format!("{:.4}", r.compression_ratio))
+                .unwrap_or_default(),
+            report
+                .map(|r| format!("{:.1}", r.wall_time_seconds))
+                .unwrap_or_default(),
+            entry
+                .error
+                .as_ref()
+                .map(|e| e.message.clone())
+                .unwrap_or_default(),
+        ];
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    std::fs::write(destination, csv).map_err(|e| format!("Failed to write CSV file: {}", e))?;
+    Ok(entries.len())
+}
@@ -0,0 +1,131 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Job id reduced to characters that are safe in a file name. Job ids come
+/// from the frontend.
+pub fn safe_file_id(job_id: &str) -> String {
+    job_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+/// Log file for a single conversion run. Logging is best effort: if the
+/// file cannot be created the conversion still runs and writes are no-ops.
+pub struct JobLog {
+    file: Option<File>,
+}
+
+impl JobLog {
+    pub fn create(dir: Option<&Path>, job_id: &str) -> Self {
+        let file = dir.and_then(|dir| {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                eprintln!("[JobLog] Failed to create log directory: {}", e);
+                return None;
+            }
+
+            let path = dir.join(format!("{}_{}.log", safe_file_id(job_id), unix_now()));
+
+            File::create(&path)
+                .map_err(|e| eprintln!("[JobLog] Failed to create {}: {}", path.display(), e))
+                .ok()
+        });
+
+        let mut log = Self { file };
+        log.line(&format!(
+            "Job {} started at {} (unix time)",
+            job_id,
+            unix_now()
+        ));
+        log
+    }
+
+    pub fn line(&mut self, text: &str) {
+        if let Some(file) = self.file.as_mut() {
+            let _ = writeln!(file, "{}", text);
+        }
+    }
+
+    pub fn section(&mut self, title: &str) {
+        self.line("");
+        self.line(&format!("--- {} ---", title));
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JobLogEntry {
+    pub name: String,
+    pub job_id: String, // As written in the file name, see safe_file_id
+    pub size: u64,
+    pub modified: u64, // Unix seconds
+}
+
+/// Rejects anything that is not a plain file name inside the log directory.
+fn log_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty()
+        || name.contains(['/', '\\'])
+        || name.contains("..")
+        || !name.ends_with(".log")
+    {
+        return Err(format!("Invalid log name: {}", name));
+    }
+    Ok(dir.join(name))
+}
+
+/// Lists job logs, newest first, optionally only those of one job.
+pub fn list_logs(dir: &Path, job_id: Option<&str>) -> Result<Vec<JobLogEntry>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read log directory: {}", e))?;
+    let wanted = job_id.map(safe_file_id);
+
+    let mut logs: Vec<JobLogEntry> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let stem = name.strip_suffix(".log")?;
+            let (id, _) = stem.rsplit_once('_')?;
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+
+            Some(JobLogEntry {
+                job_id: id.to_string(),
+                name,
+                size: metadata.len(),
+                modified,
+            })
+        })
+        .filter(|log| wanted.as_ref().map(|id| log.job_id == *id).unwrap_or(true))
+        .collect();
+
+    logs.sort_by_key(|log| std::cmp::Reverse(log.modified));
+    Ok(logs)
+}
+
+pub fn read_log(dir: &Path, name: &str) -> Result<String, String> {
+    let path = log_path(dir, name)?;
+    std::fs::read_to_string(&path).map_err(|e| format!("Failed to read log {}: {}", name, e))
+}
+
+pub fn delete_log(dir: &Path, name: &str) -> Result<(), String> {
+    let path = log_path(dir, name)?;
+    std::fs::remove_file(&path).map_err(|e| format!("Failed to delete log {}: {}", name, e))
+}
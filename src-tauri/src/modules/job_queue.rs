@@ -0,0 +1,538 @@
+use crate::modules::ffmpeg_error::ConversionError;
+use crate::modules::ffmpeg_runner::{ConversionOptions, ConversionReport};
+use crate::modules::preflight::PreflightDecision;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Paused, // Waiting in the queue but skipped by the scheduler
+    Running,
+    Suspended, // Running, but the ffmpeg process is stopped
+    Completed,
+    CompletedWithWarnings, // Output written, but with dropped tracks or a failed verification
+    Failed,
+    Cancelled,
+    Interrupted, // Was running when the app quit or crashed
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed
+                | JobStatus::CompletedWithWarnings
+                | JobStatus::Failed
+                | JobStatus::Cancelled
+                | JobStatus::Interrupted
+        )
+    }
+
+    /// Statuses that own an ffmpeg process.
+    pub fn has_process(&self) -> bool {
+        matches!(self, JobStatus::Running | JobStatus::Suspended)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConversionJob {
+    pub id: String,
+    pub options: ConversionOptions,
+    pub status: JobStatus,
+    pub progress: f64, // 0.0 to 100.0
+    pub error: Option<ConversionError>,
+    #[serde(default)]
+    pub warnings: Vec<String>, // Non-fatal issues, e.g. dropped subtitle tracks
+    #[serde(default)]
+    pub report: Option<ConversionReport>, // Set once the job completed
+    #[serde(default)]
+    pub preflight: Option<PreflightDecision>, // Set once the input was probed
+    #[serde(default)]
+    pub resolved_output: Option<String>, // Set when auto-suffixed away from options.output_path
+}
+
+impl ConversionJob {
+    /// Where the output was written, which can differ from the requested path.
+    pub fn output_path(&self) -> &str {
+        self.resolved_output
+            .as_deref()
+            .unwrap_or(&self.options.output_path)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueSnapshot {
+    pub jobs: Vec<ConversionJob>,
+    pub max_parallel: usize,
+}
+
+pub struct JobQueue {
+    jobs: Vec<ConversionJob>,
+    max_parallel: usize,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self {
+            jobs: Vec::new(),
+            max_parallel: 1,
+        }
+    }
+
+    /// Restores the queue saved by `save`. Jobs that were running when the
+    /// app went away are marked as interrupted so the user can restart them.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read queue file: {}", e))?;
+        let snapshot: QueueSnapshot = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse queue file: {}", e))?;
+
+        let mut queue = Self::new();
+        queue.set_max_parallel(snapshot.max_parallel);
+        queue.jobs = snapshot
+            .jobs
+            .into_iter()
+            .map(|mut job| {
+                if job.status.has_process() {
+                    job.status = JobStatus::Interrupted;
+                }
+                job
+            })
+            .collect();
+        Ok(queue)
+    }
+
+    /// Writes queued, running, failed and interrupted jobs to `path`.
+    /// Completed and cancelled jobs are not worth keeping across restarts.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let snapshot = QueueSnapshot {
+            jobs: self
+                .jobs
+                .iter()
+                .filter(|j| {
+                    !matches!(
+                        j.status,
+                        JobStatus::Completed
+                            | JobStatus::CompletedWithWarnings
+                            | JobStatus::Cancelled
+                    )
+                })
+                .cloned()
+                .collect(),
+            max_parallel: self.max_parallel,
+        };
+        let content = serde_json::to_string_pretty(&snapshot)
+            .map_err(|e| format!("Failed to serialize queue: {}", e))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create queue directory: {}", e))?;
+        }
+
+        // Write then rename so a crash mid-write never leaves a truncated file
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)
+            .map_err(|e| format!("Failed to write queue file: {}", e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to save queue file: {}", e))
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            jobs: self.jobs.clone(),
+            max_parallel: self.max_parallel,
+        }
+    }
+
+    pub fn set_max_parallel(&mut self, max_parallel: usize) {
+        self.max_parallel = max_parallel.max(1);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ConversionJob> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    pub fn first_with_status(&self, status: JobStatus) -> Option<&ConversionJob> {
+        self.jobs.iter().find(|j| j.status == status)
+    }
+
+    fn get_mut(&mut self, id: &str) -> Option<&mut ConversionJob> {
+        self.jobs.iter_mut().find(|j| j.id == id)
+    }
+
+    /// Adds a job at the end of the queue. A finished job with the same id is
+    /// replaced so a file can be converted again.
+    pub fn enqueue(&mut self, options: ConversionOptions) -> Result<(), String> {
+        if let Some(existing) = self.get(&options.id) {
+            if !existing.status.is_finished() {
+                return Err(format!("Job {} is already in the queue", options.id));
+            }
+            self.jobs.retain(|j| j.id != options.id);
+        }
+
+        self.jobs.push(ConversionJob {
+            id: options.id.clone(),
+            options,
+            status: JobStatus::Queued,
+            progress: 0.0,
+            error: None,
+            warnings: Vec::new(),
+            report: None,
+            preflight: None,
+            resolved_output: None,
+        });
+        Ok(())
+    }
+
+    /// Removes a job that is not currently running.
+    pub fn remove(&mut self, id: &str) -> Result<ConversionJob, String> {
+        let position = self
+            .jobs
+            .iter()
+            .position(|j| j.id == id)
+            .ok_or_else(|| format!("Job {} not found", id))?;
+
+        if self.jobs[position].status.has_process() {
+            return Err(format!("Job {} is running, cancel it first", id));
+        }
+
+        Ok(self.jobs.remove(position))
+    }
+
+    /// Moves a job to `new_index`, clamped to the end of the queue.
+    pub fn move_job(&mut self, id: &str, new_index: usize) -> Result<(), String> {
+        let position = self
+            .jobs
+            .iter()
+            .position(|j| j.id == id)
+            .ok_or_else(|| format!("Job {} not found", id))?;
+
+        let job = self.jobs.remove(position);
+        let new_index = new_index.min(self.jobs.len());
+        self.jobs.insert(new_index, job);
+        Ok(())
+    }
+
+    pub fn pause(&mut self, id: &str) -> Result<(), String> {
+        let job = self
+            .get_mut(id)
+            .ok_or_else(|| format!("Job {} not found", id))?;
+
+        match job.status {
+            JobStatus::Queued => {
+                job.status = JobStatus::Paused;
+                Ok(())
+            }
+            JobStatus::Running => {
+                job.status = JobStatus::Suspended;
+                Ok(())
+            }
+            JobStatus::Paused | JobStatus::Suspended => Ok(()),
+            _ => Err(format!(
+                "Only queued or running jobs can be paused (job {})",
+                id
+            )),
+        }
+    }
+
+    pub fn resume(&mut self, id: &str) -> Result<(), String> {
+        let job = self
+            .get_mut(id)
+            .ok_or_else(|| format!("Job {} not found", id))?;
+
+        match job.status {
+            JobStatus::Paused => {
+                job.status = JobStatus::Queued;
+                Ok(())
+            }
+            JobStatus::Suspended => {
+                job.status = JobStatus::Running;
+                Ok(())
+            }
+            JobStatus::Queued | JobStatus::Running => Ok(()),
+            _ => Err(format!("Only paused jobs can be resumed (job {})", id)),
+        }
+    }
+
+    /// Puts a failed or interrupted job back in the queue with its original options.
+    pub fn restart(&mut self, id: &str) -> Result<(), String> {
+        let job = self
+            .get_mut(id)
+            .ok_or_else(|| format!("Job {} not found", id))?;
+
+        match job.status {
+            JobStatus::Failed | JobStatus::Interrupted | JobStatus::Cancelled => {
+                job.status = JobStatus::Queued;
+                job.progress = 0.0;
+                job.error = None;
+                Ok(())
+            }
+            _ => Err(format!("Job {} cannot be restarted", id)),
+        }
+    }
+
+    /// Marks every running job as interrupted, used right before killing them on quit.
+    pub fn interrupt_running(&mut self) {
+        for job in self.jobs.iter_mut() {
+            if job.status.has_process() {
+                job.status = JobStatus::Interrupted;
+            }
+        }
+    }
+
+    /// Suspended jobs keep their slot, pausing is meant to free the machine.
+    pub fn running_count(&self) -> usize {
+        self.jobs.iter().filter(|j| j.status.has_process()).count()
+    }
+
+    /// Marks the first queued job as running and returns its options, unless
+    /// the parallel limit is already reached.
+    pub fn start_next(&mut self) -> Option<ConversionOptions> {
+        if self.running_count() >= self.max_parallel {
+            return None;
+        }
+
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|j| j.status == JobStatus::Queued)?;
+        job.status = JobStatus::Running;
+        job.progress = 0.0;
+        job.error = None;
+        job.warnings.clear();
+        job.report = None;
+        job.preflight = None;
+        job.resolved_output = None;
+        Some(job.options.clone())
+    }
+
+    pub fn set_status(&mut self, id: &str, status: JobStatus, error: Option<ConversionError>) {
+        if let Some(job) = self.get_mut(id) {
+            job.status = status;
+            job.error = error;
+            if matches!(
+                status,
+                JobStatus::Completed | JobStatus::CompletedWithWarnings
+            ) {
+                job.progress = 100.0;
+            }
+        }
+    }
+
+    pub fn set_progress(&mut self, id: &str, progress: f64) {
+        if let Some(job) = self.get_mut(id) {
+            job.progress = progress;
+        }
+    }
+
+    /// Records where the output actually went, e.g. after an auto-suffix.
+    /// The options keep the requested path so a restart resolves it again.
+    pub fn set_resolved_output(&mut self, id: &str, output_path: String) {
+        if let Some(job) = self.get_mut(id) {
+            job.resolved_output = Some(output_path);
+        }
+    }
+
+    pub fn set_report(&mut self, id: &str, report: ConversionReport) {
+        if let Some(job) = self.get_mut(id) {
+            job.report = Some(report);
+        }
+    }
+
+    pub fn set_preflight(&mut self, id: &str, decision: PreflightDecision) {
+        if let Some(job) = self.get_mut(id) {
+            job.preflight = Some(decision);
+        }
+    }
+
+    pub fn add_warning(&mut self, id: &str, warning: String) {
+        if let Some(job) = self.get_mut(id) {
+            job.warnings.push(warning);
+        }
+    }
+
+    /// Drops completed, failed and cancelled jobs from the queue.
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|j| !j.status.is_finished());
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn options(id: &str) -> ConversionOptions {
+        serde_json::from_value(json!({
+            "id": id,
+            "input_path": format!("{}.mkv", id),
+            "output_path": format!("{}.mp4", id),
+            "video_codec": "libx264",
+            "duration_seconds": 60.0,
+        }))
+        .unwrap()
+    }
+
+    fn queue(ids: &[&str]) -> JobQueue {
+        let mut queue = JobQueue::new();
+        for id in ids {
+            queue.enqueue(options(id)).unwrap();
+        }
+        queue
+    }
+
+    fn status(queue: &JobQueue, id: &str) -> JobStatus {
+        queue.get(id).unwrap().status
+    }
+
+    #[test]
+    fn starts_jobs_in_order_up_to_the_limit() {
+        let mut queue = queue(&["a", "b", "c"]);
+        queue.set_max_parallel(2);
+
+        assert_eq!(queue.start_next().unwrap().id, "a");
+        assert_eq!(queue.start_next().unwrap().id, "b");
+        assert!(queue.start_next().is_none());
+        assert_eq!(queue.running_count(), 2);
+        assert_eq!(status(&queue, "c"), JobStatus::Queued);
+
+        // A finished job frees its slot
+        queue.set_status("a", JobStatus::Completed, None);
+        assert_eq!(queue.start_next().unwrap().id, "c");
+        assert!(queue.start_next().is_none());
+    }
+
+    #[test]
+    fn limit_is_at_least_one() {
+        let mut queue = queue(&["a", "b"]);
+        queue.set_max_parallel(0);
+        assert_eq!(queue.start_next().unwrap().id, "a");
+        assert!(queue.start_next().is_none());
+    }
+
+    #[test]
+    fn moved_jobs_start_first() {
+        let mut queue = queue(&["a", "b", "c"]);
+        queue.set_max_parallel(3);
+        queue.move_job("c", 0).unwrap();
+        assert_eq!(queue.start_next().unwrap().id, "c");
+
+        // Past the end is the end
+        queue.move_job("a", 10).unwrap();
+        assert_eq!(queue.start_next().unwrap().id, "b");
+    }
+
+    #[test]
+    fn only_finished_jobs_are_replaced() {
+        let mut queue = queue(&["a"]);
+        assert!(queue.enqueue(options("a")).is_err());
+
+        queue.start_next();
+        assert!(queue.remove("a").is_err());
+        queue.set_status("a", JobStatus::Failed, None);
+        queue.enqueue(options("a")).unwrap();
+        assert_eq!(status(&queue, "a"), JobStatus::Queued);
+        assert_eq!(queue.snapshot().jobs.len(), 1);
+    }
+
+    // Saves and loads through a file of its own in the temp directory
+    fn reload(queue: &JobQueue, name: &str) -> JobQueue {
+        let path = std::env::temp_dir()
+            .join(format!("queue-test-{}-{}", std::process::id(), name))
+            .join("queue.json");
+        queue.save(&path).unwrap();
+        let loaded = JobQueue::load(&path);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        loaded.unwrap()
+    }
+
+    #[test]
+    fn keeps_unfinished_jobs_across_restarts() {
+        let mut queue = queue(&["done", "failed", "queued", "paused"]);
+        queue.set_max_parallel(2);
+        queue.start_next();
+        queue.set_status("done", JobStatus::Completed, None);
+        queue.start_next();
+        queue.set_progress("failed", 42.0);
+        queue.set_status("failed", JobStatus::Failed, None);
+        queue.add_warning("failed", "Subtitle track 3 dropped".to_string());
+        queue.pause("paused").unwrap();
+
+        let restored = reload(&queue, "round-trip");
+        let snapshot = restored.snapshot();
+        assert_eq!(snapshot.max_parallel, 2);
+        let ids: Vec<&str> = snapshot.jobs.iter().map(|j| j.id.as_str()).collect();
+        assert_eq!(ids, ["failed", "queued", "paused"]);
+
+        let failed = restored.get("failed").unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.progress, 42.0);
+        assert_eq!(failed.warnings, ["Subtitle track 3 dropped"]);
+        assert_eq!(failed.options.input_path, "failed.mkv");
+        assert_eq!(status(&restored, "paused"), JobStatus::Paused);
+    }
+
+    #[test]
+    fn running_jobs_come_back_interrupted() {
+        let mut queue = queue(&["running", "suspended", "queued"]);
+        queue.set_max_parallel(2);
+        queue.start_next();
+        queue.start_next();
+        queue.pause("suspended").unwrap();
+
+        let mut restored = reload(&queue, "interrupted");
+        assert_eq!(status(&restored, "running"), JobStatus::Interrupted);
+        assert_eq!(status(&restored, "suspended"), JobStatus::Interrupted);
+        assert_eq!(restored.running_count(), 0);
+
+        // They wait for a restart, the queued job goes first
+        assert_eq!(restored.start_next().unwrap().id, "queued");
+        restored.restart("running").unwrap();
+        assert_eq!(restored.start_next().unwrap().id, "running");
+    }
+
+    #[test]
+    fn pausing_a_queued_job_skips_it() {
+        let mut queue = queue(&["a", "b"]);
+        queue.pause("a").unwrap();
+        assert_eq!(status(&queue, "a"), JobStatus::Paused);
+        assert_eq!(queue.start_next().unwrap().id, "b");
+
+        queue.set_status("b", JobStatus::Completed, None);
+        queue.resume("a").unwrap();
+        assert_eq!(status(&queue, "a"), JobStatus::Queued);
+        assert_eq!(queue.start_next().unwrap().id, "a");
+    }
+
+    #[test]
+    fn pausing_a_running_job_keeps_its_slot() {
+        let mut queue = queue(&["a", "b"]);
+        queue.start_next();
+        queue.pause("a").unwrap();
+        assert_eq!(status(&queue, "a"), JobStatus::Suspended);
+        assert!(queue.start_next().is_none());
+
+        // Pausing twice is harmless, so is resuming twice
+        queue.pause("a").unwrap();
+        queue.resume("a").unwrap();
+        queue.resume("a").unwrap();
+        assert_eq!(status(&queue, "a"), JobStatus::Running);
+    }
+
+    #[test]
+    fn finished_jobs_cannot_be_paused() {
+        let mut queue = queue(&["a"]);
+        queue.start_next();
+        queue.set_status("a", JobStatus::Completed, None);
+        assert!(queue.pause("a").is_err());
+        assert!(queue.resume("a").is_err());
+        assert!(queue.pause("missing").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::modules::command_builder::ConvertedAudio;
+use crate::modules::conversion_types::LoudnessTarget;
+use serde::{Deserialize, Serialize};
+
+/// Lines loudnorm prints per track when ffmpeg exits: the
+/// "[loudnorm@trackN @ ...]" header and the JSON object.
+pub const LINES_PER_TRACK: usize = 14;
+
+// Instance name prefix of the analysis filters. ffmpeg numbers unnamed
+// filters across the whole graph, downmix filters included, so the chain
+// number is given explicitly.
+const INSTANCE_PREFIX: &str = "loudnorm@track";
+
+/// What the measurement pass found for one output audio track.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoudnessMeasurement {
+    pub stream_index: u32, // Input stream
+    #[serde(default)]
+    pub track: usize, // Output audio track, after any downmix
+    pub integrated: f64,   // LUFS
+    pub true_peak: f64,    // dBTP
+    pub range: f64,        // LU
+    pub threshold: f64,    // LUFS
+    pub target_offset: f64,
+}
+
+impl LoudnessMeasurement {
+    /// Silent tracks measure -inf and are left as they are.
+    pub fn is_usable(&self) -> bool {
+        [
+            self.integrated,
+            self.true_peak,
+            self.range,
+            self.threshold,
+            self.target_offset,
+        ]
+        .iter()
+        .all(|v| v.is_finite())
+    }
+}
+
+/// Analysis filter of chain `n` in the first pass, it prints what it
+/// measured as JSON.
+pub fn measure_filter(target: &LoudnessTarget, n: usize) -> String {
+    format!(
+        "{}{}=I={}:TP={}:LRA={}:print_format=json",
+        INSTANCE_PREFIX, n, target.integrated, target.true_peak, target.range
+    )
+}
+
+/// Second pass filter. With the measured values loudnorm can apply a plain
+/// gain instead of compressing on the fly. It works at 192 kHz, so the
+/// output is resampled back.
+pub fn normalize_filter(target: &LoudnessTarget, measured: &LoudnessMeasurement) -> String {
+    format!(
+        "loudnorm=I={}:TP={}:LRA={}:measured_I={:.2}:measured_TP={:.2}:measured_LRA={:.2}:measured_thresh={:.2}:offset={:.2}:linear=true,aresample=48000",
+        target.integrated,
+        target.true_peak,
+        target.range,
+        measured.integrated,
+        measured.true_peak,
+        measured.range,
+        measured.threshold,
+        measured.target_offset
+    )
+}
+
+fn field(json: &serde_json::Value, key: &str) -> Result<f64, String> {
+    json[key]
+        .as_str()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| format!("loudnorm output has no {}", key))
+}
+
+/// Reads the JSON blocks of the measurement pass. The filter named
+/// "loudnorm@trackN" measured `tracks[N]`.
+pub fn parse_measurements<'a>(
+    lines: impl Iterator<Item = &'a String>,
+    tracks: &[ConvertedAudio],
+) -> Result<Vec<LoudnessMeasurement>, String> {
+    let mut measurements = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for line in lines {
+        if let Some(rest) = line.split(INSTANCE_PREFIX).nth(1) {
+            let instance = rest
+                .split(|c: char| !c.is_ascii_digit())
+                .next()
+                .and_then(|n| n.parse().ok());
+            current = instance.map(|n| (n, String::new()));
+            continue;
+        }
+
+        let Some((instance, json)) = current.as_mut() else {
+            continue;
+        };
+        json.push_str(line);
+        if line.trim() != "}" {
+            continue;
+        }
+
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| format!("Failed to parse loudnorm output: {}", e))?;
+        if let Some(audio) = tracks.get(*instance) {
+            measurements.push(LoudnessMeasurement {
+                stream_index: audio.stream_index,
+                track: audio.track,
+                integrated: field(&value, "input_i")?,
+                true_peak: field(&value, "input_tp")?,
+                range: field(&value, "input_lra")?,
+                threshold: field(&value, "input_thresh")?,
+                target_offset: field(&value, "target_offset")?,
+            });
+        }
+        current = None;
+    }
+
+    if let Some(missing) = tracks
+        .iter()
+        .find(|audio| !measurements.iter().any(|m| m.track == audio.track))
+    {
+        return Err(format!(
+            "No loudness measurement for audio track {}",
+            missing.track
+        ));
+    }
+    Ok(measurements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(n: usize, integrated: &str) -> Vec<String> {
+        let mut lines = vec![format!("[loudnorm@track{} @ 0x55d0c8a0] ", n)];
+        lines.extend(
+            [
+                "{",
+                &format!("\t\"input_i\" : \"{}\",", integrated),
+                "\t\"input_tp\" : \"-2.10\",",
+                "\t\"input_lra\" : \"6.40\",",
+                "\t\"input_thresh\" : \"-31.20\",",
+                "\t\"output_i\" : \"-23.02\",",
+                "\t\"output_tp\" : \"-4.30\",",
+                "\t\"output_lra\" : \"5.90\",",
+                "\t\"output_thresh\" : \"-33.10\",",
+                "\t\"normalization_type\" : \"dynamic\",",
+                "\t\"target_offset\" : \"0.02\"",
+                "}",
+            ]
+            .map(String::from),
+        );
+        lines
+    }
+
+    fn track(stream_index: u32, track: usize, filters: &[&str]) -> ConvertedAudio {
+        ConvertedAudio {
+            stream_index,
+            track,
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn matches_named_instances_around_downmix_filters() {
+        // 7.1 folded to 5.1 plus its stereo compatibility track, both from
+        // stream 1. The unnamed downmix filters still take Parsed_ numbers.
+        let tracks = [
+            track(1, 0, &["aformat=channel_layouts=5.1"]),
+            track(1, 1, &["pan=stereo|FL<FL+0.707*FC|FR<FR+0.707*FC"]),
+        ];
+        let mut lines = vec![
+            "[Parsed_aformat_0 @ 0x55d0c880] auto-inserting resampler".to_string(),
+            "[Parsed_pan_2 @ 0x55d0c890] Pure channel mapping detected".to_string(),
+        ];
+        lines.extend(block(1, "-18.00"));
+        lines.extend(block(0, "-20.50"));
+
+        let measured = parse_measurements(lines.iter(), &tracks).unwrap();
+        assert_eq!(measured.len(), 2);
+        let first = measured.iter().find(|m| m.track == 0).unwrap();
+        assert_eq!(first.stream_index, 1);
+        assert_eq!(first.integrated, -20.5);
+        let compat = measured.iter().find(|m| m.track == 1).unwrap();
+        assert_eq!(compat.integrated, -18.0);
+        assert_eq!(compat.true_peak, -2.1);
+        assert_eq!(compat.target_offset, 0.02);
+    }
+
+    #[test]
+    fn reports_missing_track() {
+        let tracks = [track(1, 0, &[]), track(2, 1, &[])];
+        let lines = block(0, "-20.00");
+        let error = parse_measurements(lines.iter(), &tracks).unwrap_err();
+        assert_eq!(error, "No loudness measurement for audio track 1");
+    }
+
+    #[test]
+    fn silent_track_is_not_usable() {
+        let tracks = [track(1, 0, &[])];
+        let lines = block(0, "-inf");
+        let measured = parse_measurements(lines.iter(), &tracks).unwrap();
+        assert!(!measured[0].is_usable());
+    }
+}
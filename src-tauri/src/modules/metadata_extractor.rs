@@ -7,6 +7,10 @@ pub struct AudioStream {
     pub codec_name: String,
     pub language: Option<String>,
     pub channels: u32,
+    #[serde(default)]
+    pub bit_rate: Option<u64>, // bits/s, MKV only has it in the BPS tag
+    #[serde(default)]
+    pub channel_layout: Option<String>, // ffmpeg layout name, e.g. "5.1(side)"
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,6 +20,72 @@ pub struct SubtitleStream {
     pub language: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HdrFormat {
+    Hdr10, // PQ transfer
+    Hlg,
+    DolbyVision,
+}
+
+/// SMPTE ST 2086 mastering display, chromaticities as CIE 1931 x/y and
+/// luminance in cd/m².
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct MasteringDisplay {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white_point: (f64, f64),
+    pub min_luminance: f64,
+    pub max_luminance: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    pub max_content: u32, // MaxCLL
+    pub max_average: u32, // MaxFALL
+}
+
+/// Colour description of the video stream, as ffprobe reports it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ColorInfo {
+    pub pix_fmt: Option<String>,
+    pub color_primaries: Option<String>, // e.g. "bt2020"
+    pub color_transfer: Option<String>,  // "smpte2084" for PQ, "arib-std-b67" for HLG
+    pub color_space: Option<String>,     // Matrix, e.g. "bt2020nc"
+    pub hdr: Option<HdrFormat>,
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light: Option<ContentLightLevel>,
+    pub dolby_vision_profile: Option<u32>,
+    // Base layer a non-Dolby Vision player sees: 1 HDR10, 2 SDR, 4 HLG, 0 none
+    pub dolby_vision_compatibility: Option<u32>,
+}
+
+impl ColorInfo {
+    /// Bits per component from the pixel format, e.g. 10 for "yuv420p10le"
+    /// or "p010le". Formats without a number after the 'p' are 8-bit.
+    pub fn bit_depth(&self) -> u32 {
+        self.pix_fmt
+            .as_deref()
+            .map(|fmt| fmt.trim_end_matches("le").trim_end_matches("be"))
+            .and_then(|fmt| fmt.rsplit_once('p'))
+            .and_then(|(_, bits)| bits.parse().ok())
+            .unwrap_or(8)
+    }
+
+    /// What a player without Dolby Vision sees, which is what a re-encode
+    /// keeps. Profile 8.4 has an HLG base layer, 8.2 an SDR one.
+    pub fn base_layer_hdr(&self) -> Option<HdrFormat> {
+        match (self.hdr, self.dolby_vision_compatibility) {
+            (Some(HdrFormat::DolbyVision), Some(4)) => Some(HdrFormat::Hlg),
+            (Some(HdrFormat::DolbyVision), Some(2)) => None,
+            (Some(HdrFormat::DolbyVision), _) => Some(HdrFormat::Hdr10),
+            (hdr, _) => hdr,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VideoMetadata {
     pub duration: f64,
@@ -27,6 +97,111 @@ pub struct VideoMetadata {
     pub subtitle_streams: Vec<SubtitleStream>,
     pub size: u64, // Added size field
     pub total_frames: Option<u64>, // Total number of frames in the video
+    #[serde(default)]
+    pub color: ColorInfo,
+}
+
+/// Parses ffprobe rationals such as "34000/50000".
+fn parse_rational(value: &serde_json::Value) -> Option<f64> {
+    let text = value.as_str()?;
+    match text.split_once('/') {
+        Some((num, den)) => {
+            let den = den.parse::<f64>().ok()?;
+            (den != 0.0).then_some(num.parse::<f64>().ok()? / den)
+        }
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_mastering_display(side_data: &serde_json::Value) -> Option<MasteringDisplay> {
+    let point = |x: &str, y: &str| {
+        Some((
+            parse_rational(&side_data[x])?,
+            parse_rational(&side_data[y])?,
+        ))
+    };
+    Some(MasteringDisplay {
+        red: point("red_x", "red_y")?,
+        green: point("green_x", "green_y")?,
+        blue: point("blue_x", "blue_y")?,
+        white_point: point("white_point_x", "white_point_y")?,
+        min_luminance: parse_rational(&side_data["min_luminance"])?,
+        max_luminance: parse_rational(&side_data["max_luminance"])?,
+    })
+}
+
+/// Reads mastering display, content light and Dolby Vision records from a
+/// stream or frame side data list.
+fn read_side_data(list: &serde_json::Value, color: &mut ColorInfo) {
+    for side_data in list.as_array().into_iter().flatten() {
+        match side_data["side_data_type"].as_str().unwrap_or("") {
+            "Mastering display metadata" => {
+                color.mastering_display = color
+                    .mastering_display
+                    .or_else(|| parse_mastering_display(side_data));
+            }
+            "Content light level metadata" => {
+                if let (Some(max_content), Some(max_average)) = (
+                    side_data["max_content"].as_u64(),
+                    side_data["max_average"].as_u64(),
+                ) {
+                    color.content_light = Some(ContentLightLevel {
+                        max_content: max_content as u32,
+                        max_average: max_average as u32,
+                    });
+                }
+            }
+            "DOVI configuration record" => {
+                color.dolby_vision_profile = side_data["dv_profile"].as_u64().map(|p| p as u32);
+                color.dolby_vision_compatibility = side_data["dv_bl_signal_compatibility_id"]
+                    .as_u64()
+                    .map(|c| c as u32);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_color(stream: &serde_json::Value) -> ColorInfo {
+    let text = |key: &str| stream[key].as_str().map(|s| s.to_string());
+    let mut color = ColorInfo {
+        pix_fmt: text("pix_fmt"),
+        color_primaries: text("color_primaries"),
+        color_transfer: text("color_transfer"),
+        color_space: text("color_space"),
+        ..Default::default()
+    };
+    read_side_data(&stream["side_data_list"], &mut color);
+    color
+}
+
+/// HEVC in MP4 often only carries the mastering display in the SEI of the
+/// frames, so the first frame is read when the stream has none.
+fn probe_first_frame_side_data(file_path: &str, color: &mut ColorInfo) {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "quiet",
+            "-print_format",
+            "json",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            "%+#1",
+            "-show_frames",
+            "-show_entries",
+            "frame=side_data_list",
+            file_path,
+        ])
+        .output();
+    let Ok(output) = output else { return };
+    let json: serde_json::Value = match serde_json::from_slice(&output.stdout) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+    for frame in json["frames"].as_array().into_iter().flatten() {
+        read_side_data(&frame["side_data_list"], color);
+    }
 }
 
 pub fn extract_metadata(file_path: &str) -> Result<VideoMetadata, String> {
@@ -75,7 +250,7 @@ pub fn extract_metadata(file_path: &str) -> Result<VideoMetadata, String> {
     let mut subtitle_streams = Vec::new();
 
     // First pass: collect all video streams to find the one with highest resolution
-    let mut best_video_stream: Option<(u32, u32, String)> = None; // (width, height, codec)
+    let mut best_video_stream: Option<(u32, u32, String, &serde_json::Value)> = None; // (width, height, codec, stream)
 
     if let Some(streams) = json["streams"].as_array() {
         for stream in streams {
@@ -95,19 +270,28 @@ pub fn extract_metadata(file_path: &str) -> Result<VideoMetadata, String> {
                 let current_resolution = stream_width as u64 * stream_height as u64;
                 let best_resolution = best_video_stream
                     .as_ref()
-                    .map(|(w, h, _)| *w as u64 * *h as u64)
+                    .map(|(w, h, _, _)| *w as u64 * *h as u64)
                     .unwrap_or(0);
                 
                 if current_resolution > best_resolution {
-                    best_video_stream = Some((stream_width, stream_height, codec_name.clone()));
+                    best_video_stream =
+                        Some((stream_width, stream_height, codec_name.clone(), stream));
                 }
             } else if codec_type == "audio" {
                 let channels = stream["channels"].as_u64().unwrap_or(2) as u32;
+                let bit_rate = stream["bit_rate"]
+                    .as_str()
+                    .or_else(|| stream["tags"]["BPS"].as_str())
+                    .or_else(|| stream["tags"]["BPS-eng"].as_str())
+                    .and_then(|b| b.parse::<u64>().ok());
+                let channel_layout = stream["channel_layout"].as_str().map(|s| s.to_string());
                 audio_streams.push(AudioStream {
                     index,
                     codec_name,
                     language,
                     channels,
+                    bit_rate,
+                    channel_layout,
                 });
             } else if codec_type == "subtitle" {
                 subtitle_streams.push(SubtitleStream {
@@ -120,11 +304,27 @@ pub fn extract_metadata(file_path: &str) -> Result<VideoMetadata, String> {
     }
 
     // Use the best video stream found
-    if let Some((w, h, codec)) = best_video_stream {
+    let mut color = ColorInfo::default();
+    if let Some((w, h, codec, stream)) = best_video_stream {
         width = w;
         height = h;
         video_codec = codec;
+        color = parse_color(stream);
+    }
+
+    let is_pq = color.color_transfer.as_deref() == Some("smpte2084");
+    if is_pq && color.mastering_display.is_none() {
+        probe_first_frame_side_data(file_path, &mut color);
     }
+    color.hdr = if color.dolby_vision_profile.is_some() {
+        Some(HdrFormat::DolbyVision)
+    } else if is_pq {
+        Some(HdrFormat::Hdr10)
+    } else if color.color_transfer.as_deref() == Some("arib-std-b67") {
+        Some(HdrFormat::Hlg)
+    } else {
+        None
+    };
 
     // Extract total frames from video stream
     let mut total_frames = None;
@@ -164,5 +364,6 @@ pub fn extract_metadata(file_path: &str) -> Result<VideoMetadata, String> {
         subtitle_streams,
         size,
         total_frames,
+        color,
     })
 }
@@ -1,6 +1,17 @@
+pub mod command_builder;
+pub mod conversion_types;
+pub mod crop_detect;
+pub mod ffmpeg_error;
 pub mod ffmpeg_runner;
 pub mod gpu_detector;
+pub mod history;
+pub mod job_log;
+pub mod job_queue;
+pub mod loudness;
 pub mod metadata_extractor;
+pub mod output_file;
+pub mod preflight;
+pub mod progress;
 pub mod smart_renamer;
 pub mod tmdb_client;
 pub mod window_blur;
@@ -0,0 +1,185 @@
+use crate::modules::command_builder::CommandPlan;
+use crate::modules::conversion_types::ExistingOutputPolicy;
+use crate::modules::ffmpeg_runner::ConversionOptions;
+use crate::modules::job_log::safe_file_id;
+use crate::modules::metadata_extractor::VideoMetadata;
+use std::path::{Path, PathBuf};
+
+/// Where a job writes. ffmpeg encodes into `temp`, a hidden sibling of
+/// `destination`, which is only renamed into place once it checks out.
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    pub destination: PathBuf,
+    pub temp: PathBuf,
+}
+
+/// "name (1).ext", "name (2).ext", ... for the first free name.
+fn suffixed_path(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension().and_then(|e| e.to_str());
+
+    (1..1000)
+        .map(|n| {
+            let name = match extension {
+                Some(ext) => format!("{} ({}).{}", stem, n, ext),
+                None => format!("{} ({})", stem, n),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+}
+
+/// Hidden file next to the destination. The extension is kept so ffmpeg
+/// still picks the muxer from it.
+fn temp_sibling(destination: &Path, job_id: &str) -> Option<PathBuf> {
+    let name = destination.file_name()?.to_str()?;
+    let safe_id = safe_file_id(job_id);
+    let temp_name = match destination.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!(".{}.{}.partial.{}", name, safe_id, ext),
+        None => format!(".{}.{}.partial", name, safe_id),
+    };
+    Some(destination.with_file_name(temp_name))
+}
+
+/// Applies the existing-output policy. `Ok(None)` means the job should be
+/// skipped because the destination is already there.
+pub fn resolve_output(
+    output_path: &str,
+    job_id: &str,
+    policy: ExistingOutputPolicy,
+) -> Result<Option<OutputTarget>, String> {
+    let requested = PathBuf::from(output_path);
+
+    let destination = if requested.exists() {
+        match policy {
+            ExistingOutputPolicy::Overwrite => requested,
+            ExistingOutputPolicy::Skip => return Ok(None),
+            ExistingOutputPolicy::AutoSuffix => suffixed_path(&requested)
+                .ok_or_else(|| format!("No free file name found for {}", output_path))?,
+        }
+    } else {
+        requested
+    };
+
+    let temp = temp_sibling(&destination, job_id)
+        .ok_or_else(|| format!("Invalid output path: {}", output_path))?;
+    Ok(Some(OutputTarget { destination, temp }))
+}
+
+/// Sanity check on the encoded file before it replaces anything: ffprobe
+/// must be able to read it and find a video stream with a duration.
+pub fn check_output(metadata: &VideoMetadata) -> Result<(), String> {
+    if metadata.width == 0 || metadata.height == 0 {
+        return Err("Output has no readable video stream".to_string());
+    }
+    if metadata.duration <= 0.0 {
+        return Err("Output has no duration".to_string());
+    }
+    Ok(())
+}
+
+// Allowed duration difference: the larger of 2 seconds and 1% of the input
+const DURATION_TOLERANCE_SECS: f64 = 2.0;
+const DURATION_TOLERANCE_RATIO: f64 = 0.01;
+
+/// Compares the finished output with what the plan asked for. Mismatches do
+/// not fail the job, they are reported as warnings.
+pub fn verify_output(
+    plan: &CommandPlan,
+    options: &ConversionOptions,
+    output: &VideoMetadata,
+) -> Vec<String> {
+    let mut mismatches = Vec::new();
+
+    if options.duration_seconds > 0.0 {
+        let tolerance =
+            DURATION_TOLERANCE_SECS.max(options.duration_seconds * DURATION_TOLERANCE_RATIO);
+        let difference = (output.duration - options.duration_seconds).abs();
+        if difference > tolerance {
+            mismatches.push(format!(
+                "Output duration is {:.1}s, expected {:.1}s",
+                output.duration, options.duration_seconds
+            ));
+        }
+    }
+
+    if output.video_codec != plan.expected_video_codec {
+        mismatches.push(format!(
+            "Output video codec is {}, expected {}",
+            output.video_codec, plan.expected_video_codec
+        ));
+    }
+
+    if output.audio_streams.len() != plan.expected_audio_streams {
+        mismatches.push(format!(
+            "Output has {} audio track(s), expected {}",
+            output.audio_streams.len(),
+            plan.expected_audio_streams
+        ));
+    }
+
+    if output.subtitle_streams.len() != plan.expected_subtitle_streams {
+        mismatches.push(format!(
+            "Output has {} subtitle track(s), expected {}",
+            output.subtitle_streams.len(),
+            plan.expected_subtitle_streams
+        ));
+    }
+
+    mismatches
+}
+
+/// Moves the checked temporary file over the destination.
+pub fn commit(target: &OutputTarget) -> Result<(), String> {
+    std::fs::rename(&target.temp, &target.destination).map_err(|e| {
+        format!(
+            "Failed to move output into place at {}: {}",
+            target.destination.display(),
+            e
+        )
+    })
+}
+
+/// Removes the temporary file, if ffmpeg got as far as creating it.
+pub fn discard(target: &OutputTarget) {
+    if target.temp.exists() {
+        if let Err(e) = std::fs::remove_file(&target.temp) {
+            eprintln!(
+                "[Output] Failed to delete temporary file {}: {}",
+                target.temp.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Scratch directory for the statistics of a two-pass encode. Removed with
+/// its content when dropped, however the job ends.
+pub struct PassLogDir {
+    pub path: PathBuf,
+}
+
+impl PassLogDir {
+    pub fn create(job_id: &str) -> Result<Self, String> {
+        let path = std::env::temp_dir().join(format!(
+            "ffmpeg-passlog-{}-{}",
+            safe_file_id(job_id),
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create pass log directory: {}", e))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PassLogDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            eprintln!(
+                "[Output] Failed to delete pass log directory {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::modules::metadata_extractor::VideoMetadata;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreflightAction {
+    Skip,
+    RemuxOnly, // Copy the video stream, only audio, subtitles and container change
+    FullEncode,
+}
+
+/// All set conditions must hold for a rule to fire. Unset ones match anything.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RuleConditions {
+    pub video_codecs: Vec<String>,     // ffprobe codec names, e.g. "hevc"
+    pub containers: Vec<String>,       // Input file extensions, e.g. "mkv"
+    pub min_bitrate_kbps: Option<f64>, // Overall bitrate, from size and duration
+    pub max_bitrate_kbps: Option<f64>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+    pub min_size_mb: Option<u64>,
+    pub max_size_mb: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreflightRule {
+    pub name: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    pub conditions: RuleConditions,
+    pub action: PreflightAction,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Outcome of the rules for one input, with the rule that decided it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreflightDecision {
+    pub action: PreflightAction,
+    pub rule: Option<String>, // None when no rule fired
+    pub explanation: String,
+}
+
+/// Rules used until the user saves their own. Rules only look at the
+/// input, not at what was asked for, so the skip rule ships disabled: it
+/// would also skip files meant to be downscaled, hard-subbed or converted
+/// to H.264.
+pub fn default_rules() -> Vec<PreflightRule> {
+    vec![PreflightRule {
+        name: "Already efficient".to_string(),
+        enabled: false,
+        conditions: RuleConditions {
+            video_codecs: vec!["hevc".to_string(), "av1".to_string()],
+            max_bitrate_kbps: Some(8000.0),
+            ..Default::default()
+        },
+        action: PreflightAction::Skip,
+    }]
+}
+
+pub fn load_rules(path: &Path) -> Vec<PreflightRule> {
+    if !path.exists() {
+        return default_rules();
+    }
+
+    match std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+    {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("[Preflight] Failed to load rules, using defaults: {}", e);
+            default_rules()
+        }
+    }
+}
+
+pub fn save_rules(path: &Path, rules: &[PreflightRule]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create rules directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(rules)
+        .map_err(|e| format!("Failed to serialize rules: {}", e))?;
+    std::fs::write(path, content).map_err(|e| format!("Failed to save rules: {}", e))
+}
+
+fn overall_bitrate_kbps(metadata: &VideoMetadata) -> Option<f64> {
+    (metadata.duration > 0.0 && metadata.size > 0)
+        .then(|| metadata.size as f64 * 8.0 / metadata.duration / 1000.0)
+}
+
+/// Describes why the conditions match, or None if one of them does not.
+fn match_conditions(
+    conditions: &RuleConditions,
+    metadata: &VideoMetadata,
+    input_path: &str,
+) -> Option<Vec<String>> {
+    let mut reasons = Vec::new();
+
+    if !conditions.video_codecs.is_empty() {
+        if !conditions
+            .video_codecs
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(&metadata.video_codec))
+        {
+            return None;
+        }
+        reasons.push(format!("video is {}", metadata.video_codec));
+    }
+
+    if !conditions.containers.is_empty() {
+        let extension = Path::new(input_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        if !conditions
+            .containers
+            .iter()
+            .any(|c| c.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+        {
+            return None;
+        }
+        reasons.push(format!("container is {}", extension));
+    }
+
+    if conditions.min_bitrate_kbps.is_some() || conditions.max_bitrate_kbps.is_some() {
+        let bitrate = overall_bitrate_kbps(metadata)?;
+        if conditions.min_bitrate_kbps.map(|min| bitrate < min) == Some(true)
+            || conditions.max_bitrate_kbps.map(|max| bitrate > max) == Some(true)
+        {
+            return None;
+        }
+        reasons.push(format!("bitrate is {:.0} kb/s", bitrate));
+    }
+
+    if conditions.min_height.is_some() || conditions.max_height.is_some() {
+        if conditions.min_height.map(|min| metadata.height < min) == Some(true)
+            || conditions.max_height.map(|max| metadata.height > max) == Some(true)
+        {
+            return None;
+        }
+        reasons.push(format!("height is {}px", metadata.height));
+    }
+
+    if conditions.min_size_mb.is_some() || conditions.max_size_mb.is_some() {
+        let size_mb = metadata.size / (1024 * 1024);
+        if conditions.min_size_mb.map(|min| size_mb < min) == Some(true)
+            || conditions.max_size_mb.map(|max| size_mb > max) == Some(true)
+        {
+            return None;
+        }
+        reasons.push(format!("size is {} MB", size_mb));
+    }
+
+    Some(reasons)
+}
+
+/// Applies the first enabled rule whose conditions all match. Without one
+/// the file is fully encoded.
+pub fn evaluate(
+    rules: &[PreflightRule],
+    metadata: &VideoMetadata,
+    input_path: &str,
+) -> PreflightDecision {
+    for rule in rules.iter().filter(|r| r.enabled) {
+        if let Some(reasons) = match_conditions(&rule.conditions, metadata, input_path) {
+            let action = match rule.action {
+                PreflightAction::Skip => "skipped",
+                PreflightAction::RemuxOnly => "remuxed without re-encoding",
+                PreflightAction::FullEncode => "fully encoded",
+            };
+            let because = if reasons.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", reasons.join(", "))
+            };
+            return PreflightDecision {
+                action: rule.action,
+                rule: Some(rule.name.clone()),
+                explanation: format!("Rule \"{}\" fired, {}{}", rule.name, action, because),
+            };
+        }
+    }
+
+    PreflightDecision {
+        action: PreflightAction::FullEncode,
+        rule: None,
+        explanation: "No rule matched, fully encoded".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 10 minute 1080p HEVC at 6000 kb/s overall
+    fn metadata() -> VideoMetadata {
+        serde_json::from_value(json!({
+            "duration": 600.0,
+            "width": 1920,
+            "height": 1080,
+            "format": "matroska,webm",
+            "video_codec": "hevc",
+            "audio_streams": [],
+            "subtitle_streams": [],
+            "size": 450_000_000u64,
+        }))
+        .unwrap()
+    }
+
+    fn rule(name: &str, conditions: serde_json::Value, action: &str) -> PreflightRule {
+        serde_json::from_value(json!({
+            "name": name,
+            "conditions": conditions,
+            "action": action,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let rules = [
+            rule("Small", json!({ "max_size_mb": 100 }), "skip"),
+            rule("HEVC", json!({ "video_codecs": ["HEVC"] }), "remux_only"),
+            rule("Anything", json!({}), "skip"),
+        ];
+        let decision = evaluate(&rules, &metadata(), "in.mkv");
+        assert_eq!(decision.action, PreflightAction::RemuxOnly);
+        assert_eq!(decision.rule.as_deref(), Some("HEVC"));
+        assert_eq!(
+            decision.explanation,
+            "Rule \"HEVC\" fired, remuxed without re-encoding: video is hevc"
+        );
+    }
+
+    #[test]
+    fn disabled_rules_are_ignored() {
+        let mut disabled = rule("Anything", json!({}), "skip");
+        disabled.enabled = false;
+        let decision = evaluate(&[disabled], &metadata(), "in.mkv");
+        assert_eq!(decision.action, PreflightAction::FullEncode);
+        assert_eq!(decision.rule, None);
+
+        // The shipped rule would match this file
+        let decision = evaluate(&default_rules(), &metadata(), "in.mkv");
+        assert_eq!(decision.action, PreflightAction::FullEncode);
+    }
+
+    #[test]
+    fn all_conditions_must_match() {
+        let conditions = json!({
+            "video_codecs": ["h264", "hevc"],
+            "containers": [".MKV"],
+            "min_bitrate_kbps": 5000.0,
+            "max_bitrate_kbps": 8000.0,
+            "min_height": 720,
+            "max_height": 1080,
+            "min_size_mb": 400,
+        });
+        let rules = [rule("Fits", conditions, "skip")];
+        let decision = evaluate(&rules, &metadata(), "/videos/In.Mkv");
+        assert_eq!(decision.action, PreflightAction::Skip);
+        assert_eq!(
+            decision.explanation,
+            "Rule \"Fits\" fired, skipped: video is hevc, container is mkv, \
+             bitrate is 6000 kb/s, height is 1080px, size is 429 MB"
+        );
+
+        assert_eq!(
+            evaluate(&rules, &metadata(), "in.mp4").action,
+            PreflightAction::FullEncode
+        );
+        let mut low = metadata();
+        low.size = 300_000_000;
+        assert_eq!(
+            evaluate(&rules, &low, "in.mkv").action,
+            PreflightAction::FullEncode
+        );
+    }
+
+    #[test]
+    fn bitrate_needs_a_duration() {
+        let mut metadata = metadata();
+        metadata.duration = 0.0;
+        let rules = [rule("Low", json!({ "max_bitrate_kbps": 8000.0 }), "skip")];
+        assert_eq!(
+            evaluate(&rules, &metadata, "in.mkv").action,
+            PreflightAction::FullEncode
+        );
+    }
+}
@@ -0,0 +1,564 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Window used to average encoding speed for the ETA
+const HISTORY_WINDOW_SECS: f64 = 10.0;
+
+// (elapsed seconds, frame, output seconds)
+type Sample = (f64, u64, Option<f64>);
+
+/// One block of `-progress` output, closed by a `progress=continue|end` line.
+/// Fields ffmpeg reports as "N/A" are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgressBlock {
+    pub frame: Option<u64>,
+    pub fps: Option<f64>,
+    pub bitrate: Option<String>,
+    pub total_size: Option<u64>,
+    pub out_time_us: Option<u64>,
+    pub speed: Option<String>,
+    pub finished: bool, // progress=end
+}
+
+impl ProgressBlock {
+    pub fn out_seconds(&self) -> Option<f64> {
+        self.out_time_us.map(|us| us as f64 / 1_000_000.0)
+    }
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    if value.is_empty() || value == "N/A" {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Parses "HH:MM:SS.micro" into microseconds.
+fn parse_out_time(value: &str) -> Option<u64> {
+    let mut parts = value.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || seconds < 0.0 {
+        return None;
+    }
+    Some((hours * 3600 + minutes * 60) * 1_000_000 + (seconds * 1_000_000.0).round() as u64)
+}
+
+/// Incremental parser for the key=value lines ffmpeg writes with `-progress`.
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    current: ProgressBlock,
+}
+
+impl ProgressParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one line and returns the block it completes, if any.
+    pub fn push_line(&mut self, line: &str) -> Option<ProgressBlock> {
+        let (key, value) = line.split_once('=')?;
+        let key = key.trim();
+        let value = value.trim();
+
+        match key {
+            "frame" => self.current.frame = non_empty(value).and_then(|v| v.parse().ok()),
+            "fps" => self.current.fps = non_empty(value).and_then(|v| v.parse().ok()),
+            "bitrate" => self.current.bitrate = non_empty(value).map(|v| v.to_string()),
+            "total_size" => self.current.total_size = non_empty(value).and_then(|v| v.parse().ok()),
+            // Despite its name, out_time_ms is in microseconds as well
+            "out_time_us" | "out_time_ms" => {
+                if let Some(us) = non_empty(value).and_then(|v| v.parse::<i64>().ok()) {
+                    self.current.out_time_us = u64::try_from(us).ok();
+                }
+            }
+            "out_time" if self.current.out_time_us.is_none() => {
+                self.current.out_time_us = non_empty(value).and_then(parse_out_time);
+            }
+            "speed" => self.current.speed = non_empty(value).map(|v| v.to_string()),
+            "progress" => {
+                let mut block = std::mem::take(&mut self.current);
+                block.finished = value == "end";
+                return Some(block);
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressEstimate {
+    pub progress: f64, // 0.0 to 100.0
+    pub current_seconds: Option<f64>,
+    pub average_fps: f64,
+    pub time_remaining: Option<f64>,
+}
+
+/// Turns progress blocks into a percentage and an ETA.
+///
+/// Output time against the input duration is preferred since it stays correct
+/// for variable frame rate input, where `total_frames` is only an estimate.
+/// Frame counts are the fallback while ffmpeg reports the time as N/A.
+#[derive(Debug)]
+pub struct ProgressEstimator {
+    duration_seconds: f64,
+    total_frames: Option<u64>,
+    history: VecDeque<Sample>,
+    last_progress: f64,
+}
+
+impl ProgressEstimator {
+    pub fn new(duration_seconds: f64, total_frames: Option<u64>) -> Self {
+        Self {
+            duration_seconds,
+            total_frames: total_frames.filter(|t| *t > 0),
+            history: VecDeque::new(),
+            last_progress: 0.0,
+        }
+    }
+
+    fn raw_progress(&self, block: &ProgressBlock) -> Option<f64> {
+        let by_time = block
+            .out_seconds()
+            .filter(|_| self.duration_seconds > 0.0)
+            .map(|secs| secs / self.duration_seconds * 100.0);
+        let by_frames = match (block.frame, self.total_frames) {
+            (Some(frame), Some(total)) => Some(frame as f64 / total as f64 * 100.0),
+            _ => None,
+        };
+        by_time.or(by_frames)
+    }
+
+    /// `elapsed` is the wall-clock time since the encode started, in seconds.
+    pub fn update(&mut self, block: &ProgressBlock, elapsed: f64) -> ProgressEstimate {
+        let frame = block.frame.unwrap_or(0);
+        let current_seconds = block.out_seconds();
+
+        self.history.push_back((elapsed, frame, current_seconds));
+        while let Some(&(timestamp, _, _)) = self.history.front() {
+            if elapsed - timestamp > HISTORY_WINDOW_SECS {
+                self.history.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        // Never report 100% before ffmpeg says it is done, and never go back
+        let progress = if block.finished {
+            100.0
+        } else {
+            self.raw_progress(block)
+                .map(|p| p.clamp(0.0, 99.9))
+                .unwrap_or(0.0)
+                .max(self.last_progress)
+        };
+        self.last_progress = progress;
+
+        let (oldest, newest) = (self.history.front(), self.history.back());
+        let window = match (oldest, newest) {
+            (Some(o), Some(n)) if n.0 - o.0 > 0.0 => Some((*o, *n)),
+            _ => None,
+        };
+
+        let average_fps = window
+            .map(|(o, n)| n.1.saturating_sub(o.1) as f64 / (n.0 - o.0))
+            .unwrap_or_else(|| block.fps.unwrap_or(0.0));
+
+        let time_remaining = if block.finished {
+            Some(0.0)
+        } else {
+            self.time_remaining(window, current_seconds, frame, average_fps)
+        };
+
+        ProgressEstimate {
+            progress,
+            current_seconds,
+            average_fps,
+            time_remaining,
+        }
+    }
+
+    fn time_remaining(
+        &self,
+        window: Option<(Sample, Sample)>,
+        current_seconds: Option<f64>,
+        frame: u64,
+        average_fps: f64,
+    ) -> Option<f64> {
+        // Media seconds encoded per wall-clock second over the window
+        if let (Some((o, n)), Some(current), true) =
+            (window, current_seconds, self.duration_seconds > 0.0)
+        {
+            if let (Some(start), Some(end)) = (o.2, n.2) {
+                let rate = (end - start) / (n.0 - o.0);
+                if rate > 0.0 {
+                    return Some((self.duration_seconds - current).max(0.0) / rate);
+                }
+            }
+        }
+
+        match self.total_frames {
+            Some(total) if average_fps > 0.0 => {
+                Some(total.saturating_sub(frame) as f64 / average_fps)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Position of one ffmpeg run within a job. Every pass gets a share of the
+/// bar in proportion to its expected cost, and the passes still to come are
+/// assumed to take as long per share as the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassSpan {
+    pub index: u32, // 0-based
+    pub count: u32,
+    before: f64, // Share of the job taken by the earlier passes
+    share: f64,
+}
+
+impl PassSpan {
+    /// Span of pass `index` out of passes with the given relative costs,
+    /// which must be positive.
+    pub fn new(index: usize, costs: &[f64]) -> Self {
+        let total: f64 = costs.iter().sum();
+        Self {
+            index: index as u32,
+            count: costs.len() as u32,
+            before: costs[..index].iter().sum::<f64>() / total,
+            share: costs[index] / total,
+        }
+    }
+
+    /// Rescales the estimate of this pass to the whole job. `elapsed` is the
+    /// time spent in this pass.
+    pub fn apply(&self, estimate: ProgressEstimate, elapsed: f64) -> ProgressEstimate {
+        let later = (1.0 - self.before - self.share).max(0.0);
+
+        ProgressEstimate {
+            progress: self.before * 100.0 + self.share * estimate.progress,
+            time_remaining: estimate
+                .time_remaining
+                .map(|remaining| remaining + later / self.share * (elapsed + remaining)),
+            ..estimate
+        }
+    }
+}
+
+/// Wall-clock time spent encoding. Time spent suspended is left out so the
+/// speed average, and with it the ETA, is unaffected by a pause.
+#[derive(Debug)]
+pub struct ActiveClock {
+    started: Instant,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+}
+
+impl ActiveClock {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            paused_at: None,
+            paused_total: Duration::ZERO,
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(Instant::now());
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_total += paused_at.elapsed();
+        }
+    }
+
+    /// Active seconds since `start`, frozen while paused.
+    pub fn elapsed(&self) -> f64 {
+        let end = self.paused_at.unwrap_or_else(Instant::now);
+        end.duration_since(self.started)
+            .saturating_sub(self.paused_total)
+            .as_secs_f64()
+    }
+}
+
+/// Formats seconds as HH:MM:SS.
+pub fn format_hms(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        total / 3600,
+        (total % 3600) / 60,
+        total % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Recorded from `ffmpeg -progress pipe:1` on a 60 second, 24 fps clip
+    const SAMPLE: &str = "frame=0
+fps=0.00
+stream_0_0_q=0.0
+bitrate=N/A
+total_size=48
+out_time_us=N/A
+out_time_ms=N/A
+out_time=N/A
+dup_frames=0
+drop_frames=0
+speed=N/A
+progress=continue
+frame=240
+fps=47.93
+stream_0_0_q=28.0
+bitrate=1523.4kbits/s
+total_size=1904276
+out_time_us=10000000
+out_time_ms=10000000
+out_time=00:00:10.000000
+dup_frames=0
+drop_frames=0
+speed=1.99x
+progress=continue
+frame=1440
+fps=48.02
+stream_0_0_q=-1.0
+bitrate=1498.7kbits/s
+total_size=11240960
+out_time_us=60000000
+out_time_ms=60000000
+out_time=00:01:00.000000
+dup_frames=0
+drop_frames=0
+speed=2.00x
+progress=end
+";
+
+    fn parse_all(text: &str) -> Vec<ProgressBlock> {
+        let mut parser = ProgressParser::new();
+        text.lines().filter_map(|l| parser.push_line(l)).collect()
+    }
+
+    #[test]
+    fn parses_blocks_from_recorded_output() {
+        let blocks = parse_all(SAMPLE);
+        assert_eq!(blocks.len(), 3);
+
+        assert_eq!(blocks[0].frame, Some(0));
+        assert_eq!(blocks[0].out_time_us, None);
+        assert_eq!(blocks[0].bitrate, None);
+        assert_eq!(blocks[0].speed, None);
+        assert!(!blocks[0].finished);
+
+        assert_eq!(blocks[1].frame, Some(240));
+        assert_eq!(blocks[1].fps, Some(47.93));
+        assert_eq!(blocks[1].bitrate.as_deref(), Some("1523.4kbits/s"));
+        assert_eq!(blocks[1].total_size, Some(1904276));
+        assert_eq!(blocks[1].out_seconds(), Some(10.0));
+        assert_eq!(blocks[1].speed.as_deref(), Some("1.99x"));
+
+        assert_eq!(blocks[2].frame, Some(1440));
+        assert!(blocks[2].finished);
+    }
+
+    #[test]
+    fn each_block_starts_fresh() {
+        let blocks = parse_all(
+            "frame=10\nbitrate=100kbits/s\nprogress=continue\nframe=11\nprogress=continue\n",
+        );
+        assert_eq!(blocks[1].frame, Some(11));
+        assert_eq!(blocks[1].bitrate, None);
+    }
+
+    #[test]
+    fn falls_back_to_out_time_string() {
+        let blocks = parse_all("out_time=01:02:03.500000\nprogress=continue\n");
+        assert_eq!(blocks[0].out_time_us, Some(3_723_500_000));
+    }
+
+    #[test]
+    fn ignores_negative_and_malformed_times() {
+        let blocks = parse_all(
+            "out_time_us=-9223372036854775807\nout_time=-577014:32:22.775808\nprogress=continue\n",
+        );
+        assert_eq!(blocks[0].out_time_us, None);
+        assert_eq!(parse_out_time("garbage"), None);
+    }
+
+    #[test]
+    fn ignores_lines_without_separator() {
+        let mut parser = ProgressParser::new();
+        assert_eq!(parser.push_line(""), None);
+        assert_eq!(parser.push_line("not a progress line"), None);
+    }
+
+    #[test]
+    fn estimates_from_time_and_speed() {
+        let blocks = parse_all(SAMPLE);
+        let mut estimator = ProgressEstimator::new(60.0, Some(1440));
+
+        let first = estimator.update(&blocks[0], 0.0);
+        assert_eq!(first.progress, 0.0);
+        assert_eq!(first.time_remaining, None);
+
+        let second = estimator.update(&blocks[1], 5.0);
+        assert!((second.progress - 16.666).abs() < 0.01);
+        assert_eq!(second.current_seconds, Some(10.0));
+        assert_eq!(second.average_fps, 48.0);
+        // 50 media seconds left at 2 media seconds per wall second
+        let remaining = second.time_remaining.unwrap();
+        assert!((remaining - 25.0).abs() < 0.01, "{}", remaining);
+
+        let last = estimator.update(&blocks[2], 30.0);
+        assert_eq!(last.progress, 100.0);
+        assert_eq!(last.time_remaining, Some(0.0));
+    }
+
+    #[test]
+    fn uses_frames_when_time_is_not_available() {
+        let mut estimator = ProgressEstimator::new(0.0, Some(1000));
+        let block = |frame| ProgressBlock {
+            frame: Some(frame),
+            ..Default::default()
+        };
+
+        estimator.update(&block(0), 0.0);
+        let estimate = estimator.update(&block(250), 5.0);
+        assert_eq!(estimate.progress, 25.0);
+        assert_eq!(estimate.average_fps, 50.0);
+        assert_eq!(estimate.time_remaining, Some(15.0));
+    }
+
+    #[test]
+    fn no_progress_or_eta_without_duration_or_frame_count() {
+        // Nothing to measure progress against, so no ETA either, only the speed
+        let mut estimator = ProgressEstimator::new(0.0, None);
+        let estimate = estimator.update(
+            &ProgressBlock {
+                frame: Some(500),
+                fps: Some(30.0),
+                ..Default::default()
+            },
+            1.0,
+        );
+        assert_eq!(estimate.progress, 0.0);
+        assert_eq!(estimate.average_fps, 30.0);
+        assert_eq!(estimate.time_remaining, None);
+    }
+
+    #[test]
+    fn caps_progress_for_overestimated_vfr_frame_counts() {
+        // VFR input: the frame estimate is too low, the frame ratio overshoots
+        let mut estimator = ProgressEstimator::new(0.0, Some(100));
+        let estimate = estimator.update(
+            &ProgressBlock {
+                frame: Some(150),
+                ..Default::default()
+            },
+            1.0,
+        );
+        assert_eq!(estimate.progress, 99.9);
+    }
+
+    #[test]
+    fn progress_never_goes_backwards() {
+        let mut estimator = ProgressEstimator::new(100.0, None);
+        let block = |secs: u64| ProgressBlock {
+            out_time_us: Some(secs * 1_000_000),
+            ..Default::default()
+        };
+
+        assert_eq!(estimator.update(&block(50), 1.0).progress, 50.0);
+        // out_time can jump back briefly when ffmpeg flushes B-frames
+        assert_eq!(estimator.update(&block(40), 2.0).progress, 50.0);
+    }
+
+    #[test]
+    fn drops_history_outside_the_window() {
+        let mut estimator = ProgressEstimator::new(0.0, Some(10_000));
+        let block = |frame| ProgressBlock {
+            frame: Some(frame),
+            ..Default::default()
+        };
+
+        // Slow start, then 100 fps for the last ten seconds
+        estimator.update(&block(0), 0.0);
+        estimator.update(&block(10), 10.0);
+        estimator.update(&block(1010), 20.0);
+        let estimate = estimator.update(&block(1510), 25.0);
+        assert_eq!(estimate.average_fps, 100.0);
+    }
+
+    #[test]
+    fn single_pass_is_unchanged() {
+        let estimate = ProgressEstimate {
+            progress: 40.0,
+            current_seconds: Some(40.0),
+            average_fps: 24.0,
+            time_remaining: Some(30.0),
+        };
+        let single = PassSpan::new(0, &[1.0]);
+        assert_eq!(single.apply(estimate.clone(), 20.0), estimate);
+    }
+
+    #[test]
+    fn spreads_two_passes_over_the_bar() {
+        let estimate = |progress, remaining| ProgressEstimate {
+            progress,
+            current_seconds: None,
+            average_fps: 0.0,
+            time_remaining: Some(remaining),
+        };
+        let first = PassSpan::new(0, &[1.0, 1.0]);
+        let second = PassSpan::new(1, &[1.0, 1.0]);
+
+        // Halfway through pass 1 after 10s: 10s left in it, then 20s for pass 2
+        let halfway = first.apply(estimate(50.0, 10.0), 10.0);
+        assert_eq!(halfway.progress, 25.0);
+        assert_eq!(halfway.time_remaining, Some(30.0));
+
+        // A finished first pass stops at half the bar
+        assert_eq!(first.apply(estimate(100.0, 0.0), 20.0).progress, 50.0);
+
+        let late = second.apply(estimate(80.0, 5.0), 20.0);
+        assert_eq!(late.progress, 90.0);
+        assert_eq!(late.time_remaining, Some(5.0));
+    }
+
+    #[test]
+    fn weights_passes_by_cost() {
+        let estimate = ProgressEstimate {
+            progress: 50.0,
+            current_seconds: None,
+            average_fps: 0.0,
+            time_remaining: Some(5.0),
+        };
+        // Two equal passes, then one that takes twice as long
+        let costs = [1.0, 1.0, 2.0];
+
+        // 10s per quarter of the job, then 30s for the other three quarters
+        let first = PassSpan::new(0, &costs).apply(estimate.clone(), 5.0);
+        assert_eq!(first.progress, 12.5);
+        assert_eq!(first.time_remaining, Some(35.0));
+
+        let last = PassSpan::new(2, &costs).apply(estimate, 5.0);
+        assert_eq!(last.progress, 75.0);
+        assert_eq!(last.time_remaining, Some(5.0));
+    }
+
+    #[test]
+    fn formats_hms() {
+        assert_eq!(format_hms(0.0), "00:00:00");
+        assert_eq!(format_hms(59.9), "00:00:59");
+        assert_eq!(format_hms(3723.0), "01:02:03");
+        assert_eq!(format_hms(-5.0), "00:00:00");
+    }
+}
@@ -203,10 +203,25 @@ fn parse_filename_intelligently(original: &str) -> ParsedFilename {
     parsed
 }
 
+fn resolution_tag(height: u32) -> String {
+    if height >= 2160 {
+        "2160p".to_string()
+    } else if height >= 1080 {
+        "1080p".to_string()
+    } else if height >= 720 {
+        "720p".to_string()
+    } else if height >= 480 {
+        "480p".to_string()
+    } else {
+        "SD".to_string()
+    }
+}
+
 pub fn generate_smart_filename(
     original: &str,
     metadata: &VideoMetadata,
     output_video_codec: Option<&str>,
+    output_size: Option<(u32, u32)>,
     container: &str,
 ) -> String {
     // Parse the original filename intelligently
@@ -239,20 +254,14 @@ pub fn generate_smart_filename(
     // Get year from parsed
     let year = parsed.year.unwrap_or_default();
 
-    // Get resolution (prefer parsed, fallback to metadata)
-    let resolution = parsed.resolution.unwrap_or_else(|| {
-        if metadata.height >= 2160 {
-            "2160p".to_string()
-        } else if metadata.height >= 1080 {
-            "1080p".to_string()
-        } else if metadata.height >= 720 {
-            "720p".to_string()
-        } else if metadata.height >= 480 {
-            "480p".to_string()
-        } else {
-            "SD".to_string()
+    // Get resolution (a resized output wins, then parsed, fallback to metadata)
+    let resolution = match output_size {
+        // Wide films lose height to cropping, so the width counts as a 16:9 height
+        Some((width, height)) if (width, height) != (metadata.width, metadata.height) => {
+            resolution_tag(height.max(width * 9 / 16))
         }
-    });
+        _ => parsed.resolution.unwrap_or_else(|| resolution_tag(metadata.height)),
+    };
 
     // Get video codec (use output codec if provided, otherwise parsed or input)
     let video_codec_str = if let Some(output_codec) = output_video_codec {
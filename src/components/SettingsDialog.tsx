@@ -46,6 +46,9 @@ export const SettingsDialog: React.FC<SettingsDialogProps> = ({ isOpen: controll
     const [showApiKey, setShowApiKey] = useState(false);
     const [defaultPresetId, setDefaultPresetId] = useState('default-high');
     const [defaultOutputDir, setDefaultOutputDir] = useState('');
+    const [existingOutputPolicy, setExistingOutputPolicy] = useState('overwrite');
+    const [preflightEnabled, setPreflightEnabled] = useState(true);
+    const [autoCropEnabled, setAutoCropEnabled] = useState(false);
 
     // Presets
     const [presets, setPresets] = useState<Preset[]>([]);
@@ -76,6 +79,15 @@ export const SettingsDialog: React.FC<SettingsDialogProps> = ({ isOpen: controll
                 const outputDir = await _store.get<string>('default_output_dir');
                 if (outputDir) setDefaultOutputDir(outputDir);
 
+                const policy = await _store.get<string>('existing_output_policy');
+                if (policy) setExistingOutputPolicy(policy);
+
+                const preflight = await _store.get<boolean>('preflight_enabled');
+                if (preflight !== null && preflight !== undefined) setPreflightEnabled(preflight);
+
+                const autoCrop = await _store.get<boolean>('auto_crop_enabled');
+                if (autoCrop !== null && autoCrop !== undefined) setAutoCropEnabled(autoCrop);
+
                 // Load Presets
                 const savedPresets = await _store.get<Preset[]>('presets');
                 if (savedPresets) {
@@ -100,6 +112,9 @@ export const SettingsDialog: React.FC<SettingsDialogProps> = ({ isOpen: controll
             await store.set('tmdb_api_key', apiKey);
             await store.set('default_preset_id', defaultPresetId);
             await store.set('default_output_dir', defaultOutputDir);
+            await store.set('existing_output_policy', existingOutputPolicy);
+            await store.set('preflight_enabled', preflightEnabled);
+            await store.set('auto_crop_enabled', autoCropEnabled);
             await store.set('presets', presets);
             await store.save();
             setIsOpen(false);
@@ -269,6 +284,155 @@ export const SettingsDialog: React.FC<SettingsDialogProps> = ({ isOpen: controll
                                 </div>
                             </div>
 
+                            <div className="space-y-2">
+                                <label className="text-sm font-medium">Video Mode</label>
+                                <Select
+                                    value={editingPreset.video.remux ? 'remux' : 'encode'}
+                                    onChange={(val) => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, remux: val === 'remux' } })}
+                                    options={[
+                                        { value: 'encode', label: 'Encode' },
+                                        { value: 'remux', label: 'Remux (copy the video, change the container)' }
+                                    ]}
+                                />
+                                <p className="text-xs text-muted-foreground">
+                                    Remuxing finishes in seconds. Codec, quality and speed settings are ignored, audio and subtitle strategies still apply.
+                                </p>
+                            </div>
+
+                            {!editingPreset.video.remux && (
+                                <div className="space-y-2">
+                                    <label className="text-sm font-medium">Target Size (MB)</label>
+                                    <Input
+                                        type="number"
+                                        min="1"
+                                        value={editingPreset.video.targetSizeMb ?? ''}
+                                        onChange={e => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, targetSizeMb: e.target.value ? parseFloat(e.target.value) : undefined } })}
+                                        placeholder="None (quality based)"
+                                    />
+                                    <p className="text-xs text-muted-foreground">
+                                        Encodes twice to fit the file under this size, e.g. for upload limits. Replaces the quality setting and needs libx264, libx265, libaom-av1 or libvpx-vp9.
+                                    </p>
+                                </div>
+                            )}
+
+                            {!editingPreset.video.remux && (
+                                <div className="grid grid-cols-3 gap-4">
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">Rate Control</label>
+                                        <Select
+                                            value={editingPreset.video.rateControl || 'quality'}
+                                            onChange={(val) => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, rateControl: val as any } })}
+                                            options={[
+                                                { value: 'quality', label: 'Quality (CRF)' },
+                                                { value: 'constant_bitrate', label: 'Constant Bitrate' }
+                                            ]}
+                                        />
+                                    </div>
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">
+                                            {editingPreset.video.rateControl === 'constant_bitrate' ? 'Bitrate' : 'Max Bitrate'}
+                                        </label>
+                                        <Input
+                                            type="text"
+                                            value={(editingPreset.video.rateControl === 'constant_bitrate' ? editingPreset.video.bitrate : editingPreset.video.maxBitrate) || ''}
+                                            onChange={e => setEditingPreset({
+                                                ...editingPreset,
+                                                video: editingPreset.video.rateControl === 'constant_bitrate'
+                                                    ? { ...editingPreset.video, bitrate: e.target.value || undefined }
+                                                    : { ...editingPreset.video, maxBitrate: e.target.value || undefined }
+                                            })}
+                                            placeholder={editingPreset.video.rateControl === 'constant_bitrate' ? '4M' : 'None'}
+                                        />
+                                    </div>
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">Buffer Size</label>
+                                        <Input
+                                            type="text"
+                                            value={editingPreset.video.bufferSize || ''}
+                                            onChange={e => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, bufferSize: e.target.value || undefined } })}
+                                            placeholder="Auto"
+                                        />
+                                    </div>
+                                </div>
+                            )}
+
+                            {!editingPreset.video.remux && (
+                                <div className="grid grid-cols-2 gap-4">
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">Max Resolution</label>
+                                        <Select
+                                            value={editingPreset.video.filters?.max_width ? `${editingPreset.video.filters.max_width}x${editingPreset.video.filters.max_height}` : 'keep'}
+                                            onChange={(val) => {
+                                                const [width, height] = val === 'keep' ? [undefined, undefined] : val.split('x').map(Number);
+                                                setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, filters: { ...editingPreset.video.filters, max_width: width, max_height: height } } });
+                                            }}
+                                            options={[
+                                                { value: 'keep', label: 'Keep original' },
+                                                { value: '3840x2160', label: '2160p (3840x2160)' },
+                                                { value: '1920x1080', label: '1080p (1920x1080)' },
+                                                { value: '1280x720', label: '720p (1280x720)' },
+                                                { value: '854x480', label: '480p (854x480)' }
+                                            ]}
+                                        />
+                                    </div>
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">Letterbox</label>
+                                        <Select
+                                            value={editingPreset.video.filters?.pad_width ? 'pad' : 'none'}
+                                            onChange={(val) => {
+                                                const filters = editingPreset.video.filters;
+                                                const pad = val === 'pad' && filters?.max_width;
+                                                setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, filters: { ...filters, pad_width: pad ? filters?.max_width : undefined, pad_height: pad ? filters?.max_height : undefined } } });
+                                            }}
+                                            options={[
+                                                { value: 'none', label: 'None' },
+                                                { value: 'pad', label: 'Pad to the max resolution' }
+                                            ]}
+                                        />
+                                    </div>
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">Frame Rate</label>
+                                        <Select
+                                            value={editingPreset.video.filters?.fps ? String(editingPreset.video.filters.fps) : 'keep'}
+                                            onChange={(val) => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, filters: { ...editingPreset.video.filters, fps: val === 'keep' ? undefined : parseFloat(val) } } })}
+                                            options={[
+                                                { value: 'keep', label: 'Keep original' },
+                                                { value: '23.976', label: '23.976' },
+                                                { value: '24', label: '24' },
+                                                { value: '25', label: '25' },
+                                                { value: '29.97', label: '29.97' },
+                                                { value: '30', label: '30' },
+                                                { value: '50', label: '50' },
+                                                { value: '60', label: '60' }
+                                            ]}
+                                        />
+                                    </div>
+                                    <div className="space-y-2">
+                                        <label className="text-sm font-medium">Deinterlace</label>
+                                        <Select
+                                            value={editingPreset.video.filters?.deinterlace ? 'on' : 'off'}
+                                            onChange={(val) => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, filters: { ...editingPreset.video.filters, deinterlace: val === 'on' } } })}
+                                            options={[
+                                                { value: 'off', label: 'Off' },
+                                                { value: 'on', label: 'On (bwdif)' }
+                                            ]}
+                                        />
+                                    </div>
+                                    <div className="space-y-2 col-span-2">
+                                        <label className="text-sm font-medium">HDR Sources</label>
+                                        <Select
+                                            value={editingPreset.video.hdrMode || 'auto'}
+                                            onChange={(val) => setEditingPreset({ ...editingPreset, video: { ...editingPreset.video, hdrMode: val === 'auto' ? undefined : val as any } })}
+                                            options={[
+                                                { value: 'auto', label: 'Auto (keep HDR when the codec allows it)' },
+                                                { value: 'tone_map', label: 'Tone-map to SDR' },
+                                                { value: 'preserve', label: 'Keep HDR10 (10-bit HEVC or AV1)' }
+                                            ]}
+                                        />
+                                    </div>
+                                </div>
+                            )}
+
                             <div className="grid grid-cols-2 gap-4">
                                 <div className="space-y-2">
                                     <div className="flex justify-between">
@@ -366,6 +530,87 @@ export const SettingsDialog: React.FC<SettingsDialogProps> = ({ isOpen: controll
                                     />
                                 </div>
                             </div>
+
+                            <div className="grid grid-cols-2 gap-4">
+                                <div className="space-y-2">
+                                    <label className="text-sm font-medium">Channel Layout</label>
+                                    <Select
+                                        value={editingPreset.audio.channelLayout || 'keep'}
+                                        onChange={(val) => setEditingPreset({ ...editingPreset, audio: { ...editingPreset.audio, channelLayout: val === 'keep' ? undefined : val as any } })}
+                                        disabled={editingPreset.audio.codec === 'copy' || editingPreset.audio.strategy === 'copy_all'}
+                                        options={[
+                                            { value: 'keep', label: 'Keep Original' },
+                                            { value: '5.1', label: '5.1 (Fold Down 7.1)' },
+                                            { value: 'stereo', label: 'Stereo Downmix' }
+                                        ]}
+                                    />
+                                </div>
+                                <div className="space-y-2">
+                                    <label className="text-sm font-medium">Stereo Compatibility Track</label>
+                                    <Select
+                                        value={editingPreset.audio.stereoCompat ? 'on' : 'off'}
+                                        onChange={(val) => setEditingPreset({ ...editingPreset, audio: { ...editingPreset.audio, stereoCompat: val === 'on' ? true : undefined } })}
+                                        options={[
+                                            { value: 'off', label: 'Off' },
+                                            { value: 'on', label: 'Add After Surround Tracks' }
+                                        ]}
+                                    />
+                                </div>
+                            </div>
+
+                            {editingPreset.audio.codec !== 'copy' && editingPreset.audio.strategy !== 'copy_all' && (
+                                <div className="space-y-2">
+                                    <label className="text-sm font-medium">Loudness Normalization</label>
+                                    <Select
+                                        value={editingPreset.audio.loudness ? 'on' : 'off'}
+                                        onChange={(val) => setEditingPreset({ ...editingPreset, audio: { ...editingPreset.audio, loudness: val === 'on' ? { integrated: -23, true_peak: -1, range: 11 } : undefined } })}
+                                        options={[
+                                            { value: 'off', label: 'Off' },
+                                            { value: 'on', label: 'EBU R128 (two-pass loudnorm)' }
+                                        ]}
+                                    />
+                                    {editingPreset.audio.loudness && (
+                                        <div className="grid grid-cols-3 gap-4">
+                                            <div className="space-y-2">
+                                                <label className="text-xs font-medium text-muted-foreground">Integrated (LUFS)</label>
+                                                <Input
+                                                    type="number"
+                                                    min="-70"
+                                                    max="-5"
+                                                    step="0.5"
+                                                    value={editingPreset.audio.loudness.integrated}
+                                                    onChange={e => setEditingPreset({ ...editingPreset, audio: { ...editingPreset.audio, loudness: { ...editingPreset.audio.loudness!, integrated: parseFloat(e.target.value) } } })}
+                                                />
+                                            </div>
+                                            <div className="space-y-2">
+                                                <label className="text-xs font-medium text-muted-foreground">True Peak (dBTP)</label>
+                                                <Input
+                                                    type="number"
+                                                    min="-9"
+                                                    max="0"
+                                                    step="0.5"
+                                                    value={editingPreset.audio.loudness.true_peak}
+                                                    onChange={e => setEditingPreset({ ...editingPreset, audio: { ...editingPreset.audio, loudness: { ...editingPreset.audio.loudness!, true_peak: parseFloat(e.target.value) } } })}
+                                                />
+                                            </div>
+                                            <div className="space-y-2">
+                                                <label className="text-xs font-medium text-muted-foreground">Range (LU)</label>
+                                                <Input
+                                                    type="number"
+                                                    min="1"
+                                                    max="50"
+                                                    step="1"
+                                                    value={editingPreset.audio.loudness.range}
+                                                    onChange={e => setEditingPreset({ ...editingPreset, audio: { ...editingPreset.audio, loudness: { ...editingPreset.audio.loudness!, range: parseFloat(e.target.value) } } })}
+                                                />
+                                            </div>
+                                        </div>
+                                    )}
+                                    <p className="text-xs text-muted-foreground">
+                                        Measures every converted track first, then adjusts it to the target. -23 LUFS is the broadcast standard, -16 suits phones and streaming.
+                                    </p>
+                                </div>
+                            )}
                         </div>
                     ) : activeTab === 'general' ? (
                         <div className="space-y-6">
@@ -464,6 +709,52 @@ export const SettingsDialog: React.FC<SettingsDialogProps> = ({ isOpen: controll
                                     </p>
                                 </div>
                             </div>
+
+                            <div className="space-y-2">
+                                <label className="text-sm font-medium">When the Output File Exists</label>
+                                <Select
+                                    value={existingOutputPolicy}
+                                    onChange={(val) => setExistingOutputPolicy(val)}
+                                    options={[
+                                        { value: 'overwrite', label: 'Overwrite' },
+                                        { value: 'skip', label: 'Skip the video' },
+                                        { value: 'auto_suffix', label: 'Add a number to the name' }
+                                    ]}
+                                />
+                                <p className="text-xs text-muted-foreground">
+                                    Existing files are only replaced once the new conversion has finished and been checked.
+                                </p>
+                            </div>
+
+                            <div className="space-y-2">
+                                <label className="text-sm font-medium">Preflight Rules</label>
+                                <Select
+                                    value={preflightEnabled ? 'enabled' : 'disabled'}
+                                    onChange={(val) => setPreflightEnabled(val === 'enabled')}
+                                    options={[
+                                        { value: 'enabled', label: 'Skip or remux videos that are already optimal' },
+                                        { value: 'disabled', label: 'Always encode' }
+                                    ]}
+                                />
+                                <p className="text-xs text-muted-foreground">
+                                    By default HEVC and AV1 videos under 8 Mb/s are skipped. Rules are stored in preflight_rules.json in the app data folder.
+                                </p>
+                            </div>
+
+                            <div className="space-y-2">
+                                <label className="text-sm font-medium">Auto Crop</label>
+                                <Select
+                                    value={autoCropEnabled ? 'enabled' : 'disabled'}
+                                    onChange={(val) => setAutoCropEnabled(val === 'enabled')}
+                                    options={[
+                                        { value: 'enabled', label: 'Detect and remove black bars' },
+                                        { value: 'disabled', label: 'Keep the full picture' }
+                                    ]}
+                                />
+                                <p className="text-xs text-muted-foreground">
+                                    Samples a few points of each video before encoding. The crop is only applied when most samples agree, and a crop set on a file always wins.
+                                </p>
+                            </div>
                             <div className="space-y-3">
                                 <h3 className="text-sm font-medium">Hardware Acceleration</h3>
                                 <div className="grid grid-cols-2 gap-2">
@@ -1,7 +1,7 @@
 import React from 'react';
 import { motion, AnimatePresence } from 'framer-motion';
-import { FileVideo, Trash2, Play, Settings as SettingsIcon, FolderOpen, Wand2, Search, Square, X } from 'lucide-react';
-import { useVideoStore } from '../store/videoStore';
+import { FileVideo, Trash2, Play, Pause, Settings as SettingsIcon, FolderOpen, Wand2, Search, Square, X, Crop } from 'lucide-react';
+import { useVideoStore, LoudnessMeasurement } from '../store/videoStore';
 import { invoke } from '@tauri-apps/api/core';
 import { getCurrentWebview } from '@tauri-apps/api/webview';
 import { open } from '@tauri-apps/plugin-dialog';
@@ -13,6 +13,11 @@ import { Button } from './ui/Button';
 import { Input } from './ui/Input';
 import { extractMovieTitleAndYear } from '../utils/metadataUtils';
 
+// Same units as the size shown when files are added
+const formatBytes = (bytes: number) => bytes > 1024 * 1024 * 1024
+    ? `${(bytes / (1024 * 1024 * 1024)).toFixed(2)} GB`
+    : `${(bytes / (1024 * 1024)).toFixed(2)} MB`;
+
 // Extract FileItem to a memoized component to prevent re-renders of all items when one changes
 const FileItem = React.memo(({
     file,
@@ -40,17 +45,57 @@ const FileItem = React.memo(({
     defaultPresetId: string,
     updateStatus: (id: string, status: 'idle' | 'queued' | 'converting' | 'completed' | 'error') => void,
     updateProgress: (id: string, progress: number) => void,
-    setConversionDetails: React.Dispatch<React.SetStateAction<Record<string, { fps: number, time: string, elapsed_time: string, speed: string, time_remaining?: string }>>>,
+    setConversionDetails: React.Dispatch<React.SetStateAction<Record<string, { fps: number, time: string, elapsed_time: string, speed: string, time_remaining?: string, paused?: boolean }>>>,
     startNextConversion: () => void
 }) => {
     const isExpanded = expandedFileId === file.id;
     const details = conversionDetails[file.id];
+    const [detectingCrop, setDetectingCrop] = React.useState(false);
+    const crop = file.conversionSettings?.filters?.crop;
+
+    // Samples the file for black bars and keeps the rectangle in the file settings
+    const detectCrop = async () => {
+        setDetectingCrop(true);
+        try {
+            const detection = await invoke<{ crop?: { width: number, height: number, x: number, y: number }, confidence: number, samples: number }>('detect_crop_command', { filePath: file.path });
+            console.log(`Crop detection for ${file.name}`, detection);
+            updateFileSettings(file.id, { filters: { ...file.conversionSettings?.filters, crop: detection.crop ?? undefined } });
+        } catch (e) {
+            console.error('Failed to detect crop', e);
+        } finally {
+            setDetectingCrop(false);
+        }
+    };
 
     // Helper to format duration
     const formatDuration = (seconds: number) => {
         return new Date(seconds * 1000).toISOString().substr(11, 8);
     };
 
+    // 'abort' deletes the partial output, 'stop_and_keep' lets ffmpeg finalize it
+    const cancelConversion = async (mode: 'abort' | 'stop_and_keep') => {
+        try {
+            console.log(`Cancelling conversion for ${file.name} (ID: ${file.id}, mode: ${mode})`);
+            await invoke('cancel_conversion_command', { id: file.id, mode });
+        } catch (e) {
+            console.error('Failed to cancel conversion', e);
+        }
+        // Even if cancel fails, mark as idle and try next
+        updateStatus(file.id, 'idle');
+        // Reset progress to 0
+        updateProgress(file.id, 0);
+        // Clear conversion details for this file
+        setConversionDetails(prev => {
+            const next = { ...prev };
+            delete next[file.id];
+            return next;
+        });
+        // Start next conversion in queue after a short delay
+        setTimeout(() => {
+            startNextConversion();
+        }, 200);
+    };
+
     return (
         <motion.div
             layout
@@ -117,6 +162,30 @@ const FileItem = React.memo(({
                                     En attente
                                 </span>
                             )}
+                            {file.warnings?.length > 0 && (
+                                <span
+                                    className="px-1.5 py-0.5 rounded-full bg-orange-500/10 text-orange-500 text-[10px] font-medium border border-orange-500/20"
+                                    title={file.warnings.join('\n')}
+                                >
+                                    {file.warnings.length} avertissement{file.warnings.length > 1 ? 's' : ''}
+                                </span>
+                            )}
+                            {file.preflight && file.preflight.action !== 'full_encode' && (
+                                <span
+                                    className="px-1.5 py-0.5 rounded-full bg-sky-500/10 text-sky-500 text-[10px] font-medium border border-sky-500/20"
+                                    title={file.preflight.explanation}
+                                >
+                                    {file.preflight.action === 'skip' ? 'Ignoré' : 'Remux'}
+                                </span>
+                            )}
+                            {file.metadata?.color?.hdr && (
+                                <span
+                                    className="px-1.5 py-0.5 rounded-full bg-purple-500/10 text-purple-500 text-[10px] font-medium border border-purple-500/20"
+                                    title={`${file.metadata.color.color_transfer ?? ''} ${file.metadata.color.color_primaries ?? ''}`.trim()}
+                                >
+                                    {({ hdr10: 'HDR10', hlg: 'HLG', dolby_vision: 'Dolby Vision' } as Record<string, string>)[file.metadata.color.hdr]}
+                                </span>
+                            )}
                         </div>
                         <div className="flex items-center gap-3 text-xs text-muted-foreground">
                             {file.size && file.size !== "Unknown" && (
@@ -131,6 +200,20 @@ const FileItem = React.memo(({
                                     {formatDuration(file.duration)}
                                 </span>
                             )}
+                            {file.status === 'completed' && file.report && (
+                                <span
+                                    className="text-green-500 font-medium"
+                                    title={[
+                                        `${formatBytes(file.report.input_size)} → ${formatBytes(file.report.output_size)} • ${file.report.average_fps.toFixed(1)} fps • ${formatDuration(file.report.wall_time_seconds)}`,
+                                        ...(file.report.loudness ?? []).map((l: LoudnessMeasurement) => `Track ${l.track} (stream ${l.stream_index}): ${l.integrated.toFixed(1)} LUFS, ${l.true_peak.toFixed(1)} dBTP, ${l.range.toFixed(1)} LU before normalization`)
+                                    ].join('\n')}
+                                >
+                                    {file.report.output_size <= file.report.input_size
+                                        ? `-${formatBytes(file.report.input_size - file.report.output_size)}`
+                                        : `+${formatBytes(file.report.output_size - file.report.input_size)}`}
+                                    {' '}({Math.round(file.report.compression_ratio * 100)}%)
+                                </span>
+                            )}
                             {file.status === 'converting' && details && (
                                 <>
                                     <span className="text-primary font-medium animate-pulse">
@@ -163,6 +246,9 @@ const FileItem = React.memo(({
                                         {details.time && (
                                             <span>• Position: {details.time}</span>
                                         )}
+                                        {details.paused && (
+                                            <span className="text-yellow-500 font-medium">• En pause</span>
+                                        )}
                                         {details.time_remaining && (
                                             <span className="text-primary font-medium">• Restant: {details.time_remaining}</span>
                                         )}
@@ -183,38 +269,35 @@ const FileItem = React.memo(({
                                 size="icon"
                                 onClick={async () => {
                                     try {
-                                        console.log(`Cancelling conversion for ${file.name} (ID: ${file.id})`);
-                                        await invoke('cancel_conversion_command', { id: file.id });
-                                        updateStatus(file.id, 'idle');
-                                        // Reset progress to 0
-                                        updateProgress(file.id, 0);
-                                        // Clear conversion details for this file
-                                        setConversionDetails(prev => {
-                                            const next = { ...prev };
-                                            delete next[file.id];
-                                            return next;
-                                        });
-                                        // Start next conversion in queue after a short delay
-                                        setTimeout(() => {
-                                            startNextConversion();
-                                        }, 200);
+                                        await invoke(details?.paused ? 'resume_job_command' : 'pause_job_command', { id: file.id });
                                     } catch (e) {
-                                        console.error('Failed to cancel conversion', e);
-                                        // Even if cancel fails, mark as idle and try next
-                                        updateStatus(file.id, 'idle');
-                                        updateProgress(file.id, 0);
-                                        setConversionDetails(prev => {
-                                            const next = { ...prev };
-                                            delete next[file.id];
-                                            return next;
-                                        });
-                                        setTimeout(() => {
-                                            startNextConversion();
-                                        }, 200);
+                                        console.error('Failed to pause or resume conversion', e);
                                     }
                                 }}
+                                className="hover:bg-primary/10 hover:text-primary text-muted-foreground rounded-full"
+                                title={details?.paused ? 'Reprendre la conversion' : 'Mettre en pause'}
+                            >
+                                {details?.paused ? <Play className="w-4 h-4" /> : <Pause className="w-4 h-4" />}
+                            </Button>
+                        )}
+                        {file.status === 'converting' && (
+                            <Button
+                                variant="ghost"
+                                size="icon"
+                                onClick={() => cancelConversion('stop_and_keep')}
+                                className="hover:bg-primary/10 hover:text-primary text-muted-foreground rounded-full"
+                                title="Arrêter et garder la partie convertie"
+                            >
+                                <Square className="w-4 h-4" />
+                            </Button>
+                        )}
+                        {file.status === 'converting' && (
+                            <Button
+                                variant="ghost"
+                                size="icon"
+                                onClick={() => cancelConversion('abort')}
                                 className="hover:bg-destructive/10 hover:text-destructive text-muted-foreground rounded-full"
-                                title="Arrêter la conversion"
+                                title="Annuler la conversion"
                             >
                                 <X className="w-4 h-4" />
                             </Button>
@@ -407,6 +490,19 @@ const FileItem = React.memo(({
                                                 ]}
                                             />
                                         </div>
+                                        <div className="space-y-1">
+                                            <label className="text-xs font-medium text-muted-foreground">Channels</label>
+                                            <Select
+                                                value={file.conversionSettings?.channelLayout || 'keep'}
+                                                onChange={(val) => updateFileSettings(file.id, { channelLayout: val })}
+                                                disabled={file.conversionSettings?.audioCodec === 'copy'}
+                                                options={[
+                                                    { value: 'keep', label: 'Keep' },
+                                                    { value: '5.1', label: '5.1' },
+                                                    { value: 'stereo', label: 'Stereo Downmix' }
+                                                ]}
+                                            />
+                                        </div>
                                     </>
                                 )}
 
@@ -466,6 +562,40 @@ const FileItem = React.memo(({
                                     </div>
                                 </div>
 
+                                <div className="col-span-2 space-y-1">
+                                    <label className="text-xs font-medium text-muted-foreground">Crop Black Bars</label>
+                                    <div className="flex gap-2">
+                                        <Input
+                                            type="text"
+                                            readOnly
+                                            value={crop ? `${crop.width}x${crop.height} at ${crop.x},${crop.y}` : ''}
+                                            placeholder={detectingCrop ? 'Detecting...' : 'None (Auto Crop setting applies)'}
+                                            className="flex-1 text-xs text-muted-foreground"
+                                        />
+                                        <Button
+                                            variant="outline"
+                                            size="icon"
+                                            disabled={detectingCrop}
+                                            onClick={detectCrop}
+                                            className="hover:bg-primary/10 hover:text-primary"
+                                            title="Detect black bars"
+                                        >
+                                            <Crop className="w-3 h-3" />
+                                        </Button>
+                                        {crop && (
+                                            <Button
+                                                variant="outline"
+                                                size="icon"
+                                                onClick={() => updateFileSettings(file.id, { filters: { ...file.conversionSettings?.filters, crop: undefined } })}
+                                                className="hover:bg-destructive/10 hover:text-destructive"
+                                                title="Remove crop"
+                                            >
+                                                <X className="w-3 h-3" />
+                                            </Button>
+                                        )}
+                                    </div>
+                                </div>
+
                                 <div className="col-span-2 space-y-1">
                                     <label className="text-xs font-medium text-muted-foreground">Output Filename</label>
                                     <div className="flex gap-2">
@@ -490,9 +620,9 @@ const FileItem = React.memo(({
                                                     const currentContainer = file.conversionSettings?.container || availablePresets.find(p => p.id === defaultPresetId)?.container || 'mp4';
 
                                                     // Get output video codec (from settings or default preset)
+                                                    const defaultPreset = availablePresets.find(p => p.id === defaultPresetId);
                                                     let outputVideoCodec = file.conversionSettings?.videoCodec;
                                                     if (!outputVideoCodec || outputVideoCodec === 'default') {
-                                                        const defaultPreset = availablePresets.find(p => p.id === defaultPresetId);
                                                         outputVideoCodec = defaultPreset?.video?.codec;
                                                     }
 
@@ -501,7 +631,9 @@ const FileItem = React.memo(({
                                                         filename: file.name,
                                                         metadata: file.metadata,
                                                         outputVideoCodec: outputVideoCodec || null,
-                                                        container: currentContainer
+                                                        container: currentContainer,
+                                                        // Crop and scale change the resolution in the name
+                                                        filters: { ...defaultPreset?.video?.filters, ...file.conversionSettings?.filters }
                                                     });
 
                                                     updateFileSettings(file.id, { outputName: smartName });
@@ -560,9 +692,9 @@ const FileItem = React.memo(({
 });
 
 export const VideoList: React.FC = () => {
-    const { files, removeFile, updateStatus, updateFileSettings, updateMetadata, updateProgress } = useVideoStore();
+    const { files, addFiles, removeFile, updateStatus, updateFileSettings, updateMetadata, updateProgress } = useVideoStore();
     const [expandedFileId, setExpandedFileId] = React.useState<string | null>(null);
-    const [conversionDetails, setConversionDetails] = React.useState<Record<string, { fps: number, time: string, elapsed_time: string, speed: string, time_remaining?: string }>>({});
+    const [conversionDetails, setConversionDetails] = React.useState<Record<string, { fps: number, time: string, elapsed_time: string, speed: string, time_remaining?: string, paused?: boolean }>>({});
 
     // Metadata Search State
     const [searchDialogState, setSearchDialogState] = React.useState<{ isOpen: boolean, fileId: string | null, query: string, year?: string }>({
@@ -618,15 +750,24 @@ export const VideoList: React.FC = () => {
                         time: payload.time,
                         elapsed_time: payload.elapsed_time,
                         speed: payload.speed,
-                        time_remaining: payload.time_remaining
+                        time_remaining: payload.time_remaining,
+                        paused: payload.paused
                     }
                 }));
             }
         });
 
+        // Reported once a cancelled ffmpeg process has exited
+        const unlistenCancelled = getCurrentWebview().listen('conversion_cancelled', (event: any) => {
+            const payload = event.payload;
+            // payload: { id, mode, output_path, output_kept, message }
+            console.log(`Conversion ${payload.id} cancelled: ${payload.message}`);
+        });
+
         return () => {
             window.removeEventListener('presets-updated', handlePresetsUpdated);
             unlisten.then(f => f());
+            unlistenCancelled.then(f => f());
         };
     }, [loadPresets, updateProgress]);
 
@@ -649,26 +790,76 @@ export const VideoList: React.FC = () => {
         }
     };
 
-    // Function to start the next conversion in the queue
-    const startNextConversion = React.useCallback(async () => {
-        // Get fresh state from store to avoid stale closures
-        const currentFiles = useVideoStore.getState().files;
-        
-        // Check if there's already a conversion running
-        const isConverting = currentFiles.some(f => f.status === 'converting');
-        if (isConverting) {
-            console.log('Already converting, skipping...');
-            return; // Already converting, don't start another
-        }
+    // Files already handed to the backend queue, so they are not enqueued twice
+    const submittedRef = React.useRef<Set<string>>(new Set());
 
-        // Find the first file in queue
-        const nextFile = currentFiles.find(f => f.status === 'queued');
-        if (!nextFile) {
-            console.log('No files in queue');
-            return; // No files in queue
-        }
+    const clearConversionDetails = React.useCallback((id: string) => {
+        setConversionDetails(prev => {
+            const next = { ...prev };
+            delete next[id];
+            return next;
+        });
+    }, [setConversionDetails]);
 
-        console.log(`Starting conversion for ${nextFile.name} (ID: ${nextFile.id})`);
+    // The backend owns the queue: mirror its job states onto the file list
+    React.useEffect(() => {
+        const applyQueue = (snapshot: any, restore = false) => {
+            for (const job of snapshot?.jobs || []) {
+                // Interrupted jobs show as errors so the retry button offers a restart
+                const status = job.status === 'running' || job.status === 'suspended' ? 'converting'
+                    : job.status === 'completed' || job.status === 'completed_with_warnings' ? 'completed'
+                    : job.status === 'failed' || job.status === 'interrupted' ? 'error'
+                    : job.status === 'cancelled' ? 'idle'
+                    : 'queued';
+                let current = useVideoStore.getState().files.find(f => f.id === job.id);
+                if (!current && restore) {
+                    // Job restored from a previous session, rebuild its list entry
+                    const normalizedPath = job.options.input_path.replace(/\\/g, '/');
+                    addFiles([{
+                        id: job.id,
+                        name: normalizedPath.substring(normalizedPath.lastIndexOf('/') + 1),
+                        path: job.options.input_path,
+                        size: 'Unknown',
+                        duration: job.options.duration_seconds,
+                        totalFrames: job.options.total_frames ?? undefined,
+                        progress: job.progress,
+                        status,
+                    }]);
+                    current = useVideoStore.getState().files.find(f => f.id === job.id);
+                }
+                if (!current) continue;
+                if (job.report && current.report?.output_size !== job.report.output_size) {
+                    updateMetadata(job.id, { report: job.report });
+                }
+                if ((job.warnings || []).join('\n') !== (current.warnings || []).join('\n')) {
+                    updateMetadata(job.id, { warnings: job.warnings });
+                }
+                if ((job.preflight?.explanation ?? null) !== (current.preflight?.explanation ?? null)) {
+                    updateMetadata(job.id, { preflight: job.preflight ?? undefined });
+                }
+                if (job.status === 'completed' || job.status === 'completed_with_warnings' || job.status === 'failed' || job.status === 'cancelled' || job.status === 'interrupted') {
+                    submittedRef.current.delete(job.id);
+                    clearConversionDetails(job.id);
+                } else {
+                    submittedRef.current.add(job.id);
+                }
+                if (current.status !== status) {
+                    updateStatus(job.id, status);
+                }
+            }
+        };
+
+        invoke('get_queue_command').then(snapshot => applyQueue(snapshot, true)).catch(e => console.error('Failed to load queue', e));
+        const unlisten = getCurrentWebview().listen('queue_updated', (event: any) => applyQueue(event.payload));
+
+        return () => {
+            unlisten.then(f => f());
+        };
+    }, [addFiles, updateStatus, updateMetadata, clearConversionDetails]);
+
+    const enqueueFile = React.useCallback(async (nextFile: any) => {
+        console.log(`Enqueueing conversion for ${nextFile.name} (ID: ${nextFile.id})`);
+        submittedRef.current.add(nextFile.id);
 
         try {
             const store = await Store.load('settings.json');
@@ -683,9 +874,9 @@ export const VideoList: React.FC = () => {
             };
 
             const globalOutputDir = await store.get<string>('default_output_dir');
-
-            // Mark as converting
-            updateStatus(nextFile.id, 'converting');
+            const existingOutputPolicy = await store.get<string>('existing_output_policy') || 'overwrite';
+            const preflightEnabled = (await store.get<boolean>('preflight_enabled')) ?? true;
+            const autoCropEnabled = (await store.get<boolean>('auto_crop_enabled')) ?? false;
 
             // Determine Output Directory
             let outputDir = nextFile.conversionSettings?.outputDir || globalOutputDir;
@@ -757,70 +948,83 @@ export const VideoList: React.FC = () => {
                 ? Math.round(crfValue) as number
                 : 23;
 
-            console.log(`Starting conversion for ${nextFile.name}:`, {
+            // 'default' means "use the preset", the backend only accepts real encoder names
+            const codecOverride = nextFile.conversionSettings?.videoCodec;
+            const videoCodec = codecOverride && codecOverride !== 'default' ? codecOverride : defaultPreset.video.codec;
+            const audioStrategy = nextFile.conversionSettings?.audioStrategy || defaultPreset.audio.strategy || 'first_track';
+            const audioCodec = nextFile.conversionSettings?.audioCodec || defaultPreset.audio.codec || 'aac';
+            const audioCopied = audioCodec === 'copy' || audioStrategy === 'copy_all';
+
+            console.log(`Enqueueing conversion for ${nextFile.name}:`, {
                 input: nextFile.path,
                 output: outputPath,
-                videoCodec: nextFile.conversionSettings?.videoCodec || defaultPreset.video.codec,
+                videoCodec,
                 crf,
                 duration: nextFile.duration
             });
 
-            invoke('convert_video_command', {
+            await invoke('convert_video_command', {
                 options: {
                     id: nextFile.id, // Pass ID
                     input_path: nextFile.path,
                     output_path: outputPath,
-                    video_codec: nextFile.conversionSettings?.videoCodec || defaultPreset.video.codec,
+                    video_codec: videoCodec,
+                    remux: nextFile.conversionSettings?.remux ?? defaultPreset.video.remux ?? false,
                     audio_track_index: null, // Default
                     subtitle_track_index: null, // Default
                     duration_seconds: nextFile.duration,
                     total_frames: nextFile.totalFrames || null,
-                    audio_strategy: nextFile.conversionSettings?.audioStrategy || defaultPreset.audio.strategy || 'first_track',
+                    audio_strategy: audioStrategy,
                     subtitle_strategy: nextFile.conversionSettings?.subtitleStrategy || defaultPreset.subtitle?.strategy || 'ignore',
-                    audio_codec: nextFile.conversionSettings?.audioCodec || defaultPreset.audio.codec || 'aac',
+                    audio_codec: audioCodec,
                     audio_bitrate: nextFile.conversionSettings?.audioBitrate || defaultPreset.audio.bitrate || '128k',
+                    // Copied tracks keep their layout, the stereo track is encoded either way
+                    channel_layout: audioCopied ? null : nextFile.conversionSettings?.channelLayout || defaultPreset.audio.channelLayout || null,
+                    track_channel_layouts: null,
+                    stereo_compat_track: defaultPreset.audio.stereoCompat ?? false,
                     crf: crf,
+                    target_size_mb: nextFile.conversionSettings?.targetSizeMb ?? defaultPreset.video.targetSizeMb ?? null,
+                    rate_control: defaultPreset.video.rateControl || 'quality',
+                    video_bitrate: defaultPreset.video.rateControl === 'constant_bitrate' ? defaultPreset.video.bitrate || null : null,
+                    max_bitrate: defaultPreset.video.rateControl !== 'constant_bitrate' ? defaultPreset.video.maxBitrate || null : null,
+                    buffer_size: defaultPreset.video.rateControl === 'constant_bitrate' || defaultPreset.video.maxBitrate ? defaultPreset.video.bufferSize || null : null,
+                    hdr_mode: defaultPreset.video.hdrMode || null,
+                    // Copied audio cannot be normalized
+                    loudness: audioCopied ? null : defaultPreset.audio.loudness || null,
+                    // Filters need an encode, a remuxed file keeps its picture as is
+                    filters: (nextFile.conversionSettings?.remux ?? defaultPreset.video.remux) ? null : { ...defaultPreset.video.filters, ...nextFile.conversionSettings?.filters },
                     preset: nextFile.conversionSettings?.preset || defaultPreset.video.preset,
                     profile: nextFile.conversionSettings?.profile,
-                    tune: nextFile.conversionSettings?.tune
+                    tune: nextFile.conversionSettings?.tune,
+                    existing_output: existingOutputPolicy,
+                    preflight: preflightEnabled,
+                    auto_crop: autoCropEnabled
                 }
-            }).then(() => {
-                console.log(`Conversion completed for ${nextFile.name}`);
-                updateStatus(nextFile.id, 'completed');
-                // Clear conversion details for this file
-                setConversionDetails(prev => {
-                    const next = { ...prev };
-                    delete next[nextFile.id];
-                    return next;
-                });
-                // Start next conversion in queue
-                startNextConversion();
-            }).catch((e) => {
-                console.error(`Conversion failed for ${nextFile.name}:`, e);
-                // If cancelled manually, it might throw an error or just exit.
-                updateStatus(nextFile.id, 'error');
-                // Clear conversion details for this file
-                setConversionDetails(prev => {
-                    const next = { ...prev };
-                    delete next[nextFile.id];
-                    return next;
-                });
-                // Start next conversion in queue even if this one failed
-                startNextConversion();
             });
         } catch (e) {
-            console.error('Failed to start conversion', e);
-            // If we failed to start, mark as error and try next
+            console.error('Failed to enqueue conversion', e);
+            submittedRef.current.delete(nextFile.id);
             updateStatus(nextFile.id, 'error');
-            // Clear conversion details for this file
-            setConversionDetails(prev => {
-                const next = { ...prev };
-                delete next[nextFile.id];
-                return next;
-            });
-            startNextConversion();
+            clearConversionDetails(nextFile.id);
+        }
+    }, [updateStatus, clearConversionDetails]);
+
+    // Drop the backend job too, so a removed file is not converted later
+    const removeFileAndJob = React.useCallback((id: string) => {
+        invoke('remove_job_command', { id }).catch(() => { /* not queued */ });
+        submittedRef.current.delete(id);
+        removeFile(id);
+    }, [removeFile]);
+
+    // Hand every queued file that the backend does not know about yet to its queue
+    const startNextConversion = React.useCallback(async () => {
+        const currentFiles = useVideoStore.getState().files;
+        for (const file of currentFiles) {
+            if (file.status === 'queued' && !submittedRef.current.has(file.id)) {
+                await enqueueFile(file);
+            }
         }
-    }, [updateStatus, setConversionDetails]);
+    }, [enqueueFile]);
 
     // Function to queue all idle files for conversion
     const [isStarting, setIsStarting] = React.useState(false);
@@ -861,15 +1065,23 @@ export const VideoList: React.FC = () => {
 
 
 
+    // Space saved by every finished conversion in the list
+    const totalSaved = files.reduce((sum, f) => sum + (f.report ? f.report.input_size - f.report.output_size : 0), 0);
+
     return (
         <div className="mt-8 space-y-4">
-            <div className="flex justify-end">
+            <div className="flex items-center justify-end gap-4">
+                {totalSaved > 0 && (
+                    <span className="text-sm text-muted-foreground">
+                        Espace économisé : <span className="font-semibold text-green-500">{formatBytes(totalSaved)}</span>
+                    </span>
+                )}
                 {files.some(f => f.status === 'idle' || f.status === 'queued') && (
                     <motion.button
                         initial={{ opacity: 0, scale: 0.9 }}
                         animate={{ opacity: 1, scale: 1 }}
                         onClick={startConversion}
-                        disabled={isStarting || files.some(f => f.status === 'converting')}
+                        disabled={isStarting}
                         className="flex items-center gap-2 px-6 py-3 rounded-full bg-primary text-primary-foreground font-semibold shadow-lg hover:bg-primary/90 transition-all hover:scale-105 active:scale-95 disabled:opacity-50 disabled:cursor-not-allowed disabled:hover:scale-100"
                     >
                         <Play className="w-5 h-5 fill-current" />
@@ -886,7 +1098,7 @@ export const VideoList: React.FC = () => {
                         expandedFileId={expandedFileId}
                         toggleExpand={toggleExpand}
                         updateFileSettings={updateFileSettings}
-                        removeFile={removeFile}
+                        removeFile={removeFileAndJob}
                         conversionDetails={conversionDetails}
                         openSearchDialog={openSearchDialog}
                         availablePresets={availablePresets}
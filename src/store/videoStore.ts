@@ -1,20 +1,56 @@
 import { create } from 'zustand';
+import type { VideoFilters } from '../types/preset';
 
 export interface ConversionSettingsOverride {
     videoCodec?: string;
+    remux?: boolean; // Copy the video stream instead of encoding it
     audioStrategy?: string; // 'copy_all', 'convert_all', 'first_track'
     subtitleStrategy?: string; // 'copy_all', 'burn_in', 'ignore'
     audioCodec?: string;
     audioBitrate?: string;
+    channelLayout?: string; // 'keep', '5.1', 'stereo'
     outputDir?: string;
     outputName?: string;
     crf?: number;
+    targetSizeMb?: number;
+    filters?: VideoFilters; // Merged over the preset filters, e.g. a per-file crop
     preset?: string;
     profile?: string;
     tune?: string;
     container?: string;
 }
 
+export interface LoudnessMeasurement {
+    stream_index: number;
+    track: number; // Output audio track
+    integrated: number; // LUFS
+    true_peak: number; // dBTP
+    range: number; // LU
+    threshold: number;
+    target_offset: number;
+}
+
+// Sent by the backend when a conversion finishes
+export interface ConversionReport {
+    id: string;
+    output_path: string;
+    input_size: number; // bytes
+    output_size: number; // bytes
+    compression_ratio: number; // output_size / input_size
+    average_fps: number;
+    wall_time_seconds: number;
+    bitrate_kbps?: number;
+    command: string;
+    loudness?: LoudnessMeasurement[]; // Input loudness of the normalized tracks
+}
+
+// Outcome of the preflight rules, set once the input was probed
+export interface PreflightDecision {
+    action: 'skip' | 'remux_only' | 'full_encode';
+    rule?: string;
+    explanation: string;
+}
+
 export interface FileItem {
     id: string;
     name: string;
@@ -30,6 +66,9 @@ export interface FileItem {
     overview?: string;
     releaseDate?: string;
     conversionSettings?: ConversionSettingsOverride;
+    warnings?: string[]; // Reported by the backend, e.g. dropped tracks or a failed output check
+    report?: ConversionReport;
+    preflight?: PreflightDecision;
     // Progress details
     timeRemaining?: string;
     speed?: string;
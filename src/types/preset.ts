@@ -1,3 +1,14 @@
+// Same shape as VideoFilters in the backend, applied in this order
+export interface VideoFilters {
+    deinterlace?: boolean;
+    crop?: { width: number; height: number; x: number; y: number };
+    max_width?: number; // Downscale to fit, keeping the aspect ratio
+    max_height?: number;
+    pad_width?: number; // Centre the picture on a black frame of this size
+    pad_height?: number;
+    fps?: number;
+}
+
 export interface Preset {
     id: string;
     name: string;
@@ -5,14 +16,24 @@ export interface Preset {
     container: 'mp4' | 'mkv' | 'avi' | 'mov';
     video: {
         codec: string; // 'libx264', 'h264_nvenc', etc.
-        bitrate?: string; // '2M', '5000k'
+        remux?: boolean; // Copy the video stream, codec/crf/preset are ignored
+        bitrate?: string; // '2M', '5000k', used in constant bitrate mode
+        rateControl?: 'quality' | 'constant_bitrate';
+        maxBitrate?: string; // Caps the CRF in quality mode
+        bufferSize?: string;
         crf?: number; // 0-51
         preset?: string; // 'fast', 'slow', etc.
+        targetSizeMb?: number; // Two-pass encode to this size, replaces the CRF
+        filters?: VideoFilters;
+        hdrMode?: 'tone_map' | 'preserve'; // Unset keeps HDR when the codec can
     };
     audio: {
         codec: string; // 'aac', 'ac3', 'copy'
-        bitrate?: string; // '128k', '320k'
+        bitrate?: string; // '128k', '320k', for stereo; surround tracks get more per channel
+        channelLayout?: 'keep' | '5.1' | 'stereo'; // Wider tracks are folded down
+        stereoCompat?: boolean; // Add a stereo track after each surround one
         strategy?: 'copy_all' | 'convert_all' | 'first_track'; // New strategy
+        loudness?: { integrated: number; true_peak: number; range: number }; // EBU R128 targets, off when unset
     };
     subtitle?: {
         strategy: 'copy_all' | 'burn_in' | 'ignore'; // New strategy
@@ -48,5 +69,22 @@ export const DEFAULT_PRESETS: Preset[] = [
             codec: 'aac',
             bitrate: '128k'
         }
+    },
+    {
+        id: 'remux-mp4',
+        name: 'Remux to MP4',
+        description: 'Changes the container without re-encoding the video',
+        container: 'mp4',
+        video: {
+            codec: 'libx264',
+            remux: true
+        },
+        audio: {
+            codec: 'copy',
+            strategy: 'copy_all'
+        },
+        subtitle: {
+            strategy: 'copy_all'
+        }
     }
 ];